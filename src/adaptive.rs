@@ -0,0 +1,112 @@
+use crate::color;
+use crate::vec3::Vec3;
+
+/// Running mean and variance of the luminance of the samples of a
+/// pixel, using Welford's online algorithm.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct PixelStats {
+    pub count: u32,
+    pub(crate) mean: f32,
+    pub(crate) m2: f32,
+}
+
+impl PixelStats {
+    /// Add the radiance of a new sample.
+    pub fn add(&mut self, radiance: Vec3) {
+        let value = color::luminance(radiance);
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Combine the statistics of two disjoint sets of samples.
+    pub fn merge(self, other: Self) -> Self {
+        if self.count == 0 {
+            return other;
+        }
+        if other.count == 0 {
+            return self;
+        }
+
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let weight = self.count as f32 * other.count as f32 / count as f32;
+        Self {
+            count,
+            mean: self.mean + delta * other.count as f32 / count as f32,
+            m2: self.m2 + other.m2 + delta * delta * weight,
+        }
+    }
+
+    /// Mean luminance of the samples so far.
+    pub fn mean(&self) -> f32 {
+        self.mean
+    }
+
+    /// Unbiased sample variance of the luminance.
+    pub fn variance(&self) -> f32 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.m2 / (self.count - 1) as f32
+        }
+    }
+
+    /// Estimated standard error of the mean, relative to the mean itself
+    /// so that dark and bright pixels are held to the same standard.
+    pub fn relative_error(&self) -> f32 {
+        if self.count == 0 {
+            return f32::INFINITY;
+        }
+        (self.variance() / self.count as f32).sqrt() / (self.mean + 1e-3)
+    }
+}
+
+/// Budget and stopping criterion of adaptive sampling.
+#[derive(Debug, Copy, Clone)]
+pub struct AdaptiveSampling {
+    /// Samples taken before the error estimate is trusted.
+    pub min_samples: u32,
+    /// Samples after which a pixel is considered done regardless.
+    pub max_samples: u32,
+    /// Relative error under which a pixel stops being sampled.
+    pub threshold: f32,
+    /// Samples taken between checks of the error.
+    pub batch: u32,
+}
+
+impl AdaptiveSampling {
+    pub fn new(min_samples: u32, max_samples: u32, threshold: f32) -> Self {
+        Self {
+            min_samples: min_samples.max(2).min(max_samples),
+            max_samples,
+            threshold,
+            batch: 16,
+        }
+    }
+
+    /// Take exactly `samples` samples in every pixel.
+    pub fn fixed(samples: u32) -> Self {
+        Self {
+            min_samples: samples,
+            max_samples: samples,
+            threshold: 0.0,
+            batch: samples,
+        }
+    }
+
+    /// Number of samples to take next for a pixel, or zero if it has
+    /// converged or exhausted its budget.
+    pub fn next_batch(&self, stats: &PixelStats) -> u32 {
+        if stats.count >= self.max_samples {
+            0
+        } else if stats.count < self.min_samples {
+            self.min_samples - stats.count
+        } else if stats.relative_error() <= self.threshold {
+            0
+        } else {
+            self.batch.min(self.max_samples - stats.count)
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use crate::film::Film;
+use crate::material::Material;
+use crate::model::{Hit, Model};
+use crate::output::{self, Channel, PixelType};
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::ops::{Add, Div};
+use std::path::Path;
+
+/// Arbitrary output variables of a path, recorded at the first hit
+/// along the camera ray, next to the light split by path depth.
+///
+/// Identifiers start at one, zero being the background.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Aovs {
+    pub albedo: Vec3,
+    pub normal: Vec3,
+    pub depth: f32,
+    pub position: Vec3,
+    pub material_id: u32,
+    pub object_id: u32,
+    /// Light emitted by the first surface hit or arriving at it straight
+    /// from an emitter.
+    pub direct: Vec3,
+    /// Light that bounced at least twice before reaching the camera.
+    pub indirect: Vec3,
+}
+
+impl Aovs {
+    /// Record the surface details of the first hit of a camera ray.
+    pub fn record_first_hit(&mut self, ray: &Ray, rec: &Hit, ids: &MaterialIds) {
+        self.albedo = rec.material.albedo(rec);
+        self.normal = rec.shading_normal;
+        self.depth = rec.parameter * ray.direction.mag();
+        self.position = rec.point;
+        self.material_id = ids.get(rec.material);
+        self.object_id = rec.object;
+    }
+
+    /// Returns the radiance of the whole path.
+    pub fn radiance(&self) -> Vec3 {
+        self.direct + self.indirect
+    }
+}
+
+// Summing samples keeps the identifiers of the left hand side, as
+// identifiers cannot be averaged.
+impl Add for Aovs {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let (material_id, object_id) = if self.material_id == 0 && self.object_id == 0 {
+            (rhs.material_id, rhs.object_id)
+        } else {
+            (self.material_id, self.object_id)
+        };
+
+        Self {
+            albedo: self.albedo + rhs.albedo,
+            normal: self.normal + rhs.normal,
+            depth: self.depth + rhs.depth,
+            position: self.position + rhs.position,
+            material_id,
+            object_id,
+            direct: self.direct + rhs.direct,
+            indirect: self.indirect + rhs.indirect,
+        }
+    }
+}
+
+impl Div<f32> for Aovs {
+    type Output = Self;
+
+    fn div(self, rhs: f32) -> Self::Output {
+        Self {
+            albedo: self.albedo / rhs,
+            normal: self.normal / rhs,
+            depth: self.depth / rhs,
+            position: self.position / rhs,
+            direct: self.direct / rhs,
+            indirect: self.indirect / rhs,
+            ..self
+        }
+    }
+}
+
+/// Stable identifiers for the materials of a scene, numbered in the
+/// order they are first found.
+pub struct MaterialIds {
+    ids: HashMap<usize, u32>,
+}
+
+impl MaterialIds {
+    pub fn new(world: &Model) -> Self {
+        let mut ids = HashMap::new();
+        for material in world.materials() {
+            let next = ids.len() as u32 + 1;
+            ids.entry(Self::key(material)).or_insert(next);
+        }
+        Self { ids }
+    }
+
+    pub fn get(&self, material: &Material) -> u32 {
+        self.ids.get(&Self::key(material)).copied().unwrap_or(0)
+    }
+
+    // Materials are told apart by their address.
+    fn key(material: &Material) -> usize {
+        material as *const Material as usize
+    }
+}
+
+/// Per-pixel arbitrary output variables of a render.
+pub struct AovFilm {
+    width: u32,
+    height: u32,
+    pixels: Vec<Aovs>,
+}
+
+impl AovFilm {
+    /// Names of the passes, in the order they are written.
+    pub const PASSES: [&'static str; 8] = [
+        "albedo", "normal", "depth", "position", "material", "object", "direct", "indirect",
+    ];
+
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Aovs::default(); width as usize * height as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Aovs {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, aovs: Aovs) {
+        self.pixels[y as usize * self.width as usize + x as usize] = aovs;
+    }
+
+    /// Extract a single pass as a film, with scalar passes stored in
+    /// all three channels.
+    pub fn pass(&self, name: &str) -> Option<Film> {
+        let value: fn(&Aovs) -> Vec3 = match name {
+            "albedo" => |a| a.albedo,
+            "normal" => |a| a.normal,
+            "depth" => |a| Vec3::all(a.depth),
+            "position" => |a| a.position,
+            "material" => |a| Vec3::all(a.material_id as f32),
+            "object" => |a| Vec3::all(a.object_id as f32),
+            "direct" => |a| a.direct,
+            "indirect" => |a| a.indirect,
+            _ => return None,
+        };
+
+        let mut film = Film::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                film.set(x, y, value(&self.get(x, y)));
+            }
+        }
+        Some(film)
+    }
+
+    /// Save every pass as its own linear image, named after the pass with
+    /// the given stem and extension, such as `default_albedo.exr`.
+    pub fn save_passes(
+        &self,
+        dir: impl AsRef<Path>,
+        stem: &str,
+        extension: &str,
+    ) -> io::Result<()> {
+        for name in &Self::PASSES {
+            let path = dir
+                .as_ref()
+                .join(format!("{}_{}.{}", stem, name, extension));
+            if let Some(film) = self.pass(name) {
+                film.save(path, &Default::default(), PixelType::Half)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Save every pass as layers of a single OpenEXR image, optionally
+    /// along with the beauty render in the default layer.
+    pub fn save_exr(&self, path: impl AsRef<Path>, beauty: Option<&Film>) -> io::Result<()> {
+        let mut data: Vec<(String, Vec<f32>)> = Vec::new();
+
+        if let Some(beauty) = beauty {
+            let [r, g, b] = beauty.channels();
+            data.push(("R".to_owned(), r));
+            data.push(("G".to_owned(), g));
+            data.push(("B".to_owned(), b));
+        }
+
+        let scalar = |f: fn(&Aovs) -> f32| self.pixels.iter().map(f).collect::<Vec<_>>();
+        let mut vector = |layer: &str, names: [&str; 3], f: fn(&Aovs) -> Vec3| {
+            let values = self.pixels.iter().map(f).collect::<Vec<_>>();
+            data.push((
+                format!("{}.{}", layer, names[0]),
+                values.iter().map(|v| v.x).collect(),
+            ));
+            data.push((
+                format!("{}.{}", layer, names[1]),
+                values.iter().map(|v| v.y).collect(),
+            ));
+            data.push((
+                format!("{}.{}", layer, names[2]),
+                values.iter().map(|v| v.z).collect(),
+            ));
+        };
+
+        vector("albedo", ["R", "G", "B"], |a| a.albedo);
+        vector("N", ["X", "Y", "Z"], |a| a.normal);
+        vector("P", ["X", "Y", "Z"], |a| a.position);
+        vector("direct", ["R", "G", "B"], |a| a.direct);
+        vector("indirect", ["R", "G", "B"], |a| a.indirect);
+        data.push(("Z".to_owned(), scalar(|a| a.depth)));
+        data.push(("materialId".to_owned(), scalar(|a| a.material_id as f32)));
+        data.push(("objectId".to_owned(), scalar(|a| a.object_id as f32)));
+
+        let channels = data
+            .iter()
+            .map(|(name, values)| Channel::new(name.as_str(), values))
+            .collect::<Vec<_>>();
+        output::write_exr(
+            BufWriter::new(File::create(path)?),
+            self.width,
+            self.height,
+            &channels,
+            PixelType::Float,
+        )
+    }
+}
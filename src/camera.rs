@@ -1,5 +1,6 @@
 use crate::ray::Ray;
-use crate::vec3::{vec3, Vec3};
+use crate::sampler::{self, Sampler};
+use crate::vec3::Vec3;
 
 pub struct Camera {
     top_left_corner: Vec3,
@@ -50,18 +51,12 @@ impl Camera {
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = Self::random_in_unit_disk() * self.lens_radius;
+    pub fn get_ray(&self, s: f32, t: f32, sampler: &mut impl Sampler) -> Ray {
+        let rd = sampler::unit_disk(sampler.next_2d()) * self.lens_radius;
         let offset = self.u * rd.x + self.v * rd.y;
         Ray::new(
             self.origin + offset,
             self.top_left_corner + s * self.horizontal - t * self.vertical - self.origin - offset,
         )
     }
-
-    fn random_in_unit_disk() -> Vec3 {
-        let theta = rand::random::<f32>() * 2.0 * std::f32::consts::PI;
-        let r = rand::random::<f32>().sqrt();
-        vec3(r * theta.cos(), r * theta.sin(), 0.0)
-    }
 }
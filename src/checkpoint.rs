@@ -0,0 +1,409 @@
+use crate::adaptive::PixelStats;
+use crate::aov::Aovs;
+use crate::film::SplatFilm;
+use crate::filter::Filter;
+use crate::integrator::Integrator;
+use crate::render::{PixelCost, RenderedTile};
+use crate::tile::Tile;
+use crate::vec3::{vec3, Vec3};
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"RTCK";
+const VERSION: u32 = 7;
+
+// Longest string read, so that a corrupt length fails instead of
+// allocating gigabytes.
+const MAX_STR_LEN: usize = 1 << 16;
+
+/// Settings a render must share with a checkpoint to resume from it.
+///
+/// Samples are drawn from streams picked by the seed, pixel and sample
+/// index, so the sample counts stored for every pixel are all it takes
+/// to carry on with the same random numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub width: u32,
+    pub height: u32,
+    pub seed: u32,
+    pub sampler: String,
+    /// Samples per pixel the strata of stratified samplers are laid out
+    /// for. Renders resuming a checkpoint keep the strata it started
+    /// with, whatever sample count they go on to.
+    pub strata: u32,
+    pub filter: Filter,
+    pub radius: f32,
+    pub integrator: Integrator,
+    pub roulette_depth: Option<i32>,
+    pub clamp_direct: Option<f32>,
+    pub clamp_indirect: Option<f32>,
+    pub regularization: Option<f32>,
+}
+
+/// Save the state of a render after its pixels were sampled up to
+/// `samples` samples each.
+///
+/// The checkpoint is written next to the file and then moved over it,
+/// so that a crash while saving leaves the previous checkpoint intact.
+pub fn save(
+    path: impl AsRef<Path>,
+    settings: &Settings,
+    samples: u32,
+    tiles: &[RenderedTile],
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let partial = path.with_extension("partial");
+
+    let mut w = BufWriter::new(File::create(&partial)?);
+    w.write_all(MAGIC)?;
+    put_u32(&mut w, VERSION)?;
+    put_u32(&mut w, settings.width)?;
+    put_u32(&mut w, settings.height)?;
+    put_u32(&mut w, settings.seed)?;
+    put_str(&mut w, &settings.sampler)?;
+    put_u32(&mut w, settings.strata)?;
+    put_str(&mut w, settings.filter.name())?;
+    put_f32(&mut w, settings.radius)?;
+    put_integrator(&mut w, settings.integrator)?;
+    put_option(&mut w, settings.roulette_depth.map(|depth| depth as u32))?;
+    put_option(&mut w, settings.clamp_direct.map(f32::to_bits))?;
+    put_option(&mut w, settings.clamp_indirect.map(f32::to_bits))?;
+    put_option(&mut w, settings.regularization.map(f32::to_bits))?;
+    put_u32(&mut w, samples)?;
+
+    put_u32(&mut w, tiles.len() as u32)?;
+    for rendered in tiles {
+        write_tile(&mut w, rendered)?;
+    }
+
+    w.into_inner()?.sync_all()?;
+    fs::rename(partial, path)
+}
+
+/// Load a checkpoint, returning the number of samples its pixels were
+/// sampled up to along with its tiles, and taking on its strata.
+///
+/// Fails with `InvalidData` if the checkpoint was written by a render
+/// with different settings, apart from the strata.
+pub fn load(
+    path: impl AsRef<Path>,
+    settings: &mut Settings,
+) -> io::Result<(u32, Vec<RenderedTile>)> {
+    let mut r = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC || get_u32(&mut r)? != VERSION {
+        return Err(invalid("not a checkpoint of this version".to_owned()));
+    }
+
+    let saved = Settings {
+        width: get_u32(&mut r)?,
+        height: get_u32(&mut r)?,
+        seed: get_u32(&mut r)?,
+        sampler: get_str(&mut r)?,
+        strata: get_u32(&mut r)?,
+        filter: get_str(&mut r)?.parse().map_err(invalid)?,
+        radius: get_f32(&mut r)?,
+        integrator: get_integrator(&mut r)?,
+        roulette_depth: get_option(&mut r)?.map(|depth| depth as i32),
+        clamp_direct: get_option(&mut r)?.map(f32::from_bits),
+        clamp_indirect: get_option(&mut r)?.map(f32::from_bits),
+        regularization: get_option(&mut r)?.map(f32::from_bits),
+    };
+    let expected = Settings {
+        strata: saved.strata,
+        ..settings.clone()
+    };
+    if saved != expected {
+        return Err(invalid(format!(
+            "checkpoint was rendered with different settings: {:?}",
+            saved
+        )));
+    }
+    settings.strata = saved.strata;
+    let samples = get_u32(&mut r)?;
+
+    let film = SplatFilm::new(
+        settings.width,
+        settings.height,
+        settings.filter,
+        settings.radius,
+    );
+    let count = get_u32(&mut r)?;
+    let tiles = (0..count)
+        .map(|_| read_tile(&mut r, &film))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    Ok((samples, tiles))
+}
+
+// Integrators are written by name, followed by their parameter if they
+// have one.
+fn put_integrator(w: &mut impl Write, integrator: Integrator) -> io::Result<()> {
+    put_str(w, integrator.name())?;
+    match integrator {
+        Integrator::AmbientOcclusion { distance } => put_f32(w, distance),
+        _ => Ok(()),
+    }
+}
+
+fn get_integrator(r: &mut impl Read) -> io::Result<Integrator> {
+    match get_str(r)?.parse().map_err(invalid)? {
+        Integrator::AmbientOcclusion { .. } => Ok(Integrator::AmbientOcclusion {
+            distance: get_f32(r)?,
+        }),
+        integrator => Ok(integrator),
+    }
+}
+
+// Optional settings are written as whether they are set, followed by
+// their bits, or zero if unset.
+fn put_option(w: &mut impl Write, value: Option<u32>) -> io::Result<()> {
+    put_u32(w, value.is_some() as u32)?;
+    put_u32(w, value.unwrap_or(0))
+}
+
+fn get_option(r: &mut impl Read) -> io::Result<Option<u32>> {
+    let set = get_u32(r)? != 0;
+    let value = get_u32(r)?;
+    Ok(if set { Some(value) } else { None })
+}
+
+/// Write the samples of a rendered tile.
+pub(crate) fn write_tile(w: &mut impl Write, rendered: &RenderedTile) -> io::Result<()> {
+    put_tile(w, &rendered.tile)?;
+    let pixels = rendered
+        .stats
+        .iter()
+        .zip(&rendered.aovs)
+        .zip(&rendered.costs);
+    for ((stats, aovs), cost) in pixels {
+        put_u32(w, stats.count)?;
+        put_f32(w, stats.mean)?;
+        put_f32(w, stats.m2)?;
+        put_vec3(w, aovs.albedo)?;
+        put_vec3(w, aovs.normal)?;
+        put_f32(w, aovs.depth)?;
+        put_vec3(w, aovs.position)?;
+        put_u32(w, aovs.material_id)?;
+        put_u32(w, aovs.object_id)?;
+        put_vec3(w, aovs.direct)?;
+        put_vec3(w, aovs.indirect)?;
+        put_f32(w, cost.seconds)?;
+        put_u64(w, cost.intersection_tests)?;
+    }
+
+    let splats = &rendered.splats;
+    put_range(w, &splats.x)?;
+    put_range(w, &splats.y)?;
+    for (weighted, weight) in splats.weighted.iter().zip(&splats.weights) {
+        put_vec3(w, *weighted)?;
+        put_f32(w, *weight)?;
+    }
+    Ok(())
+}
+
+// Read a rendered tile of the image covered by the film, on a film with
+// its filter.
+fn read_tile(r: &mut impl Read, film: &SplatFilm) -> io::Result<RenderedTile> {
+    let tile = get_tile(r)?;
+    let inside = |range: &Range<u32>, bounds: &Range<u32>| {
+        bounds.start <= range.start && range.start <= range.end && range.end <= bounds.end
+    };
+    if !inside(&tile.x, &film.x) || !inside(&tile.y, &film.y) {
+        return Err(invalid(format!("tile {:?} is outside the image", tile)));
+    }
+    let mut rendered = RenderedTile::new(&tile, film.around(&tile));
+    read_samples(r, &mut rendered)?;
+    Ok(rendered)
+}
+
+/// Read the samples written by `write_tile` after its tile, into a tile
+/// without any samples yet. Fails with `InvalidData` unless they cover
+/// the same pixels.
+pub(crate) fn read_samples(r: &mut impl Read, rendered: &mut RenderedTile) -> io::Result<()> {
+    let pixels = rendered
+        .stats
+        .iter_mut()
+        .zip(&mut rendered.aovs)
+        .zip(&mut rendered.costs);
+    for ((stats, aovs), cost) in pixels {
+        *stats = PixelStats {
+            count: get_u32(r)?,
+            mean: get_f32(r)?,
+            m2: get_f32(r)?,
+        };
+        *aovs = Aovs {
+            albedo: get_vec3(r)?,
+            normal: get_vec3(r)?,
+            depth: get_f32(r)?,
+            position: get_vec3(r)?,
+            material_id: get_u32(r)?,
+            object_id: get_u32(r)?,
+            direct: get_vec3(r)?,
+            indirect: get_vec3(r)?,
+        };
+        *cost = PixelCost {
+            seconds: get_f32(r)?,
+            intersection_tests: get_u64(r)?,
+        };
+    }
+
+    let splats = &mut rendered.splats;
+    let (x, y) = (get_range(r)?, get_range(r)?);
+    if x != splats.x || y != splats.y {
+        return Err(invalid(format!("splats cover {:?} × {:?}", x, y)));
+    }
+    for (weighted, weight) in splats.weighted.iter_mut().zip(&mut splats.weights) {
+        *weighted = get_vec3(r)?;
+        *weight = get_f32(r)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn put_u32(w: &mut impl Write, value: u32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn put_u64(w: &mut impl Write, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn put_f32(w: &mut impl Write, value: f32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn put_vec3(w: &mut impl Write, value: Vec3) -> io::Result<()> {
+    put_f32(w, value.x)?;
+    put_f32(w, value.y)?;
+    put_f32(w, value.z)
+}
+
+pub(crate) fn put_range(w: &mut impl Write, range: &Range<u32>) -> io::Result<()> {
+    put_u32(w, range.start)?;
+    put_u32(w, range.end)
+}
+
+pub(crate) fn put_tile(w: &mut impl Write, tile: &Tile) -> io::Result<()> {
+    put_u32(w, tile.index as u32)?;
+    put_range(w, &tile.x)?;
+    put_range(w, &tile.y)
+}
+
+pub(crate) fn put_str(w: &mut impl Write, value: &str) -> io::Result<()> {
+    put_u32(w, value.len() as u32)?;
+    w.write_all(value.as_bytes())
+}
+
+pub(crate) fn get_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(crate) fn get_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn get_f32(r: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_bits(get_u32(r)?))
+}
+
+pub(crate) fn get_vec3(r: &mut impl Read) -> io::Result<Vec3> {
+    Ok(vec3(get_f32(r)?, get_f32(r)?, get_f32(r)?))
+}
+
+pub(crate) fn get_range(r: &mut impl Read) -> io::Result<Range<u32>> {
+    Ok(get_u32(r)?..get_u32(r)?)
+}
+
+pub(crate) fn get_tile(r: &mut impl Read) -> io::Result<Tile> {
+    Ok(Tile {
+        index: get_u32(r)? as usize,
+        x: get_range(r)?,
+        y: get_range(r)?,
+    })
+}
+
+pub(crate) fn get_str(r: &mut impl Read) -> io::Result<String> {
+    let len = get_u32(r)? as usize;
+    if len > MAX_STR_LEN {
+        return Err(invalid(format!("string of {} bytes", len)));
+    }
+    let mut bytes = vec![0; len];
+    r.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|e| invalid(e.to_string()))
+}
+
+pub(crate) fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::model::Model;
+    use crate::progress::Tracker;
+    use crate::render::tests::with_renderer;
+    use crate::sampler::SamplerKind;
+
+    // Resuming a checkpoint to more samples gives the image rendered in
+    // one go with the strata it started with.
+    #[test]
+    fn resume_to_more_samples() {
+        let ground = Material::lambertian(vec3(0.5, 0.5, 0.5));
+        let light = Material::diffuse_light(Vec3::all(4.0));
+        let world = Model::list(vec![
+            Model::sphere(vec3(0.0, -101.0, -3.0), 100.0, &ground),
+            Model::sphere(vec3(0.5, 0.5, -3.0), 0.8, &light),
+        ]);
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.ck", std::process::id()));
+        let tracker = Tracker::new(&(), 0, 0);
+
+        for &sampler in &["independent", "stratified"] {
+            with_renderer(&world, 8, |renderer, tiles| {
+                renderer.sampler = SamplerKind::from_name(sampler, 4, 1).unwrap();
+                let mut settings = Settings {
+                    width: renderer.width,
+                    height: renderer.height,
+                    seed: 1,
+                    sampler: sampler.to_owned(),
+                    strata: 4,
+                    filter: Filter::Box,
+                    radius: 0.5,
+                    integrator: renderer.integrator,
+                    roulette_depth: None,
+                    clamp_direct: None,
+                    clamp_indirect: None,
+                    regularization: None,
+                };
+                let mut rendered = renderer.start(tiles);
+                renderer.refine(&mut rendered, 4, None, &tracker);
+                save(&path, &settings, 4, &rendered).unwrap();
+                let whole = {
+                    let mut rendered = renderer.start(tiles);
+                    renderer.refine(&mut rendered, 8, None, &tracker);
+                    renderer.assemble(&rendered).film
+                };
+
+                // Resumed with a sample count of 8 on the command line.
+                settings.strata = 8;
+                let (reached, mut resumed) = load(&path, &mut settings).unwrap();
+                assert_eq!((reached, settings.strata), (4, 4));
+                renderer.refine(&mut resumed, 8, None, &tracker);
+                assert_eq!(renderer.assemble(&resumed).film.pixels(), whole.pixels());
+
+                settings.seed = 2;
+                assert!(load(&path, &mut settings).is_err());
+            });
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
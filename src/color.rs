@@ -0,0 +1,90 @@
+use crate::vec3::{vec3, Vec3};
+
+/// Returns the luminance of a linear sRGB colour, using the
+/// Rec. 709 coefficients.
+pub fn luminance(color: Vec3) -> f32 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+/// Encode a linear value with the sRGB transfer function.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decode an sRGB encoded value back to linear.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Map a value in `[0, 1]` to a false colour going from dark blue
+/// through green and yellow to red, for heatmaps. The colour is
+/// returned in linear sRGB.
+pub fn heatmap(t: f32) -> Vec3 {
+    const STOPS: [(f32, f32, f32); 5] = [
+        (0.05, 0.03, 0.35),
+        (0.0, 0.55, 0.85),
+        (0.2, 0.8, 0.2),
+        (0.95, 0.85, 0.1),
+        (0.85, 0.1, 0.05),
+    ];
+
+    let t = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (t as usize).min(STOPS.len() - 2);
+    let from = Vec3::from(STOPS[i]);
+    let to = Vec3::from(STOPS[i + 1]);
+    from.lerp(to, t - i as f32).map(srgb_to_linear)
+}
+
+/// Returns the linear sRGB colour of an ideal black body radiator at
+/// the given temperature in Kelvin, scaled to a luminance of one.
+///
+/// The Planck spectrum is integrated against the multi-lobe fit of the
+/// CIE 1931 colour matching functions by Wyman et al. (2013), and colours
+/// outside of the sRGB gamut are clipped.
+pub fn blackbody(kelvin: f32) -> Vec3 {
+    // Second radiation constant, in metre Kelvin.
+    const C2: f64 = 1.4388e-2;
+
+    let kelvin = f64::from(kelvin.max(1.0));
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+
+    for nm in (380..=780).step_by(5) {
+        let lambda = f64::from(nm);
+        let metres = lambda * 1e-9;
+        // The constant factor of Planck's law cancels out
+        // when normalising, so it is left out.
+        let radiance = 1.0 / (metres.powi(5) * ((C2 / (metres * kelvin)).exp() - 1.0));
+
+        x += radiance
+            * (1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+                - 0.065 * lobe(lambda, 501.1, 20.4, 26.2));
+        y += radiance
+            * (0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1));
+        z += radiance
+            * (1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8));
+    }
+
+    let (x, y, z) = (x / y, 1.0, z / y);
+    let rgb = vec3(
+        (3.2406 * x - 1.5372 * y - 0.4986 * z) as f32,
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z) as f32,
+        (0.0557 * x - 0.2040 * y + 1.0570 * z) as f32,
+    )
+    .map(|c| c.max(0.0));
+
+    rgb / luminance(rgb)
+}
+
+// Piecewise gaussian with different widths on either side of the mean.
+fn lobe(x: f64, mean: f64, sigma_lo: f64, sigma_hi: f64) -> f64 {
+    let t = (x - mean) / if x < mean { sigma_lo } else { sigma_hi };
+    (-0.5 * t * t).exp()
+}
@@ -0,0 +1,156 @@
+use crate::vec3::Vec3;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Length of the segments drawn for rays leaving the scene, in scene
+/// units.
+pub const ESCAPE_LENGTH: f32 = 2.0;
+
+/// What happened at the end of a segment of a path.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// Hit a surface and bounced off or through it.
+    Scattered,
+    /// Hit a surface that absorbed the path.
+    Absorbed,
+    /// Hit a surface after the maximum number of bounces, ending the path.
+    Cut,
+    /// Scattered inside a participating medium.
+    Medium,
+    /// Hit a surface or scattered inside a medium, and was then ended by
+    /// Russian roulette.
+    Roulette,
+    /// Left the scene.
+    Escaped,
+}
+
+impl Event {
+    pub fn name(self) -> &'static str {
+        match self {
+            Event::Scattered => "scattered",
+            Event::Absorbed => "absorbed",
+            Event::Cut => "cut",
+            Event::Medium => "medium",
+            Event::Roulette => "roulette",
+            Event::Escaped => "escaped",
+        }
+    }
+}
+
+/// One segment of a path, from where a ray left to where it ended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bounce {
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// Where the ray ended, or `None` if it left the scene.
+    pub point: Option<Vec3>,
+    pub event: Event,
+    /// Geometric normal of the surface hit, zero away from surfaces.
+    pub normal: Vec3,
+    /// Kind of material hit, if any.
+    pub material: Option<&'static str>,
+    /// Product of the attenuations of the path before this bounce.
+    pub throughput: Vec3,
+    /// Attenuation of the ray scattered here, zero if the path ended.
+    pub attenuation: Vec3,
+    /// Radiance emitted towards the ray, before the throughput.
+    pub emitted: Vec3,
+}
+
+/// A path traced for one sample of a pixel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracedPath {
+    pub pixel: (u32, u32),
+    pub sample: u32,
+    pub bounces: Vec<Bounce>,
+    /// Radiance the path brought back to the camera.
+    pub radiance: Vec3,
+}
+
+impl fmt::Display for TracedPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "pixel ({}, {}) sample {}: radiance {}",
+            self.pixel.0,
+            self.pixel.1,
+            self.sample,
+            Vector(self.radiance)
+        )?;
+        for (depth, bounce) in self.bounces.iter().enumerate() {
+            write!(
+                f,
+                "  {:>3} {:<9} {:<13}",
+                depth,
+                bounce.event.name(),
+                bounce.material.unwrap_or("-")
+            )?;
+            match bounce.point {
+                Some(point) => write!(f, " point {}", Vector(point))?,
+                None => write!(f, " direction {}", Vector(bounce.direction))?,
+            }
+            if bounce.material.is_some() {
+                write!(f, " normal {}", Vector(bounce.normal))?;
+            }
+            writeln!(
+                f,
+                " throughput {} attenuation {} emitted {}",
+                Vector(bounce.throughput),
+                Vector(bounce.attenuation),
+                Vector(bounce.emitted)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Write paths to a Wavefront OBJ file as polylines, one object per path,
+/// for viewing along with the scene in a 3D tool.
+///
+/// Rays leaving the scene are drawn `ESCAPE_LENGTH` long.
+pub fn save_obj(path: impl AsRef<Path>, paths: &[TracedPath]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    let mut vertices = 0;
+
+    for traced in paths {
+        writeln!(
+            w,
+            "o pixel_{}_{}_sample_{}",
+            traced.pixel.0, traced.pixel.1, traced.sample
+        )?;
+        let first = match traced.bounces.first() {
+            Some(bounce) => bounce.origin,
+            None => continue,
+        };
+        let ends = traced.bounces.iter().map(|bounce| {
+            bounce
+                .point
+                .unwrap_or_else(|| bounce.origin + bounce.direction.normalize() * ESCAPE_LENGTH)
+        });
+
+        let start = vertices + 1;
+        for v in std::iter::once(first).chain(ends) {
+            writeln!(w, "v {} {} {}", v.x, v.y, v.z)?;
+            vertices += 1;
+        }
+        write!(w, "l")?;
+        for index in start..=vertices {
+            write!(w, " {}", index)?;
+        }
+        writeln!(w)?;
+    }
+
+    w.flush()
+}
+
+// Short form of a vector for the log.
+struct Vector(Vec3);
+
+impl fmt::Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({:.4}, {:.4}, {:.4})", self.0.x, self.0.y, self.0.z)
+    }
+}
@@ -0,0 +1,135 @@
+use crate::aov::AovFilm;
+use crate::film::Film;
+use crate::vec3::Vec3;
+
+use rayon::prelude::*;
+
+/// Edge-avoiding à-trous wavelet filter, after Dammertz et al. (2010).
+///
+/// Repeatedly blurs the film with a sparse 5x5 kernel whose taps spread
+/// further apart at each iteration, while the albedo, normal and position
+/// buffers stop the blur from crossing edges in the scene.
+#[derive(Debug, Copy, Clone)]
+pub struct Denoiser {
+    /// Number of filter passes, each doubling the kernel footprint.
+    pub iterations: u32,
+    /// Tolerance to differences in colour, halved at every iteration.
+    pub sigma_color: f32,
+    pub sigma_albedo: f32,
+    pub sigma_normal: f32,
+    pub sigma_position: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 0.6,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.3,
+            sigma_position: 0.2,
+        }
+    }
+}
+
+impl Denoiser {
+    // B3 spline used for the kernel weights.
+    const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+    /// Returns a denoised copy of the film, guided by the feature
+    /// buffers of the same render.
+    pub fn denoise(&self, film: &Film, features: &AovFilm) -> Film {
+        let (width, height) = (film.width(), film.height());
+
+        // Filter the illumination only, so that the texture
+        // detail carried by the albedo stays sharp.
+        let albedo = |x, y| features.get(x, y).albedo.map(|c| c.max(1e-3));
+        let mut illumination = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| film.get(x, y) / albedo(x, y))
+            .collect::<Vec<_>>();
+
+        for iteration in 0..self.iterations {
+            let step = 1i64 << iteration;
+            let sigma_color = self.sigma_color / (1 << iteration) as f32;
+
+            illumination = (0..height)
+                .into_par_iter()
+                .flat_map(|y| {
+                    let illumination = &illumination;
+                    (0..width).into_par_iter().map(move |x| {
+                        self.filter_pixel(
+                            illumination,
+                            features,
+                            (width, height),
+                            (x, y),
+                            step,
+                            sigma_color,
+                        )
+                    })
+                })
+                .collect();
+        }
+
+        let mut denoised = Film::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let index = y as usize * width as usize + x as usize;
+                denoised.set(x, y, illumination[index] * albedo(x, y));
+            }
+        }
+        denoised
+    }
+
+    // Weighted average of the taps around a single pixel.
+    fn filter_pixel(
+        &self,
+        illumination: &[Vec3],
+        features: &AovFilm,
+        (width, height): (u32, u32),
+        (x, y): (u32, u32),
+        step: i64,
+        sigma_color: f32,
+    ) -> Vec3 {
+        let center = illumination[y as usize * width as usize + x as usize];
+        let center_features = features.get(x, y);
+
+        let mut sum = Vec3::ZERO;
+        let mut total_weight = 0.0;
+
+        for (j, ky) in Self::KERNEL.iter().enumerate() {
+            for (i, kx) in Self::KERNEL.iter().enumerate() {
+                let qx = x as i64 + (i as i64 - 2) * step;
+                let qy = y as i64 + (j as i64 - 2) * step;
+                if qx < 0 || qy < 0 || qx >= width as i64 || qy >= height as i64 {
+                    continue;
+                }
+                let (qx, qy) = (qx as u32, qy as u32);
+
+                let sample = illumination[qy as usize * width as usize + qx as usize];
+                let tap = features.get(qx, qy);
+
+                let weight = kx
+                    * ky
+                    * edge_stop(center - sample, sigma_color)
+                    * edge_stop(center_features.albedo - tap.albedo, self.sigma_albedo)
+                    * edge_stop(center_features.normal - tap.normal, self.sigma_normal)
+                    * edge_stop(center_features.position - tap.position, self.sigma_position);
+
+                sum += sample * weight;
+                total_weight += weight;
+            }
+        }
+
+        if total_weight > 0.0 {
+            sum / total_weight
+        } else {
+            center
+        }
+    }
+}
+
+// Gaussian falloff of the weight with the difference between two features.
+fn edge_stop(difference: Vec3, sigma: f32) -> f32 {
+    (-difference.mag_sq() / (sigma * sigma)).exp()
+}
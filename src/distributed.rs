@@ -0,0 +1,420 @@
+use crate::checkpoint::{get_str, get_tile, get_u32, get_u64, invalid, read_samples, write_tile};
+use crate::checkpoint::{put_str, put_tile, put_u32, put_u64};
+use crate::progress::{Observer, Tracker};
+use crate::render::{RenderedTile, Renderer};
+use crate::stats::RayStats;
+use crate::tile::Tile;
+
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const MAGIC: &[u8; 4] = b"RTDW";
+const VERSION: u32 = 4;
+
+// Messages from the coordinator to its workers, each starting with its
+// tag. Workers answer a batch of tiles with the tiles rendered and the
+// rays traced for them.
+const TILES: u32 = 1;
+const DONE: u32 = 2;
+
+// How long idle loops wait before looking for work again.
+const POLL: Duration = Duration::from_millis(20);
+
+// How long a connection may take to introduce itself as a worker, and
+// to take the messages sent to it, before it is dropped.
+const TIMEOUT: Duration = Duration::from_secs(if cfg!(test) { 1 } else { 10 });
+
+// Tiles waiting for a worker, and tiles rendered so far.
+struct Queue {
+    pending: VecDeque<Tile>,
+    done: Vec<RenderedTile>,
+}
+
+/// Render tiles on the workers connecting to `listener`, up to `target`
+/// samples per pixel each, reporting to the tracker as every tile comes
+/// back.
+///
+/// Workers are sent `job`, the settings they build the renderer from,
+/// and then batches of as many tiles as they have threads. The job only
+/// holds command line options, not the scene: workers render the
+/// built-in scene of their own build, or read the `--scene` file at the
+/// same path, which must hold the scene of the coordinator for the image
+/// to come together. The batch of a worker that fails or disconnects
+/// goes back to the queue for the others. Tiles are rendered with the same random numbers wherever they
+/// are, so the result is the same as rendering them all locally.
+///
+/// Connections that do not introduce themselves as workers in time are
+/// dropped. Once the render is cancelled, no more tiles are handed out
+/// and the tiles already back are returned.
+pub fn coordinate(
+    listener: TcpListener,
+    job: &[String],
+    renderer: &Renderer,
+    tiles: &[Tile],
+    target: u32,
+    tracker: &Tracker<impl Observer>,
+) -> io::Result<Vec<RenderedTile>> {
+    let queue = Mutex::new(Queue {
+        pending: tiles.iter().cloned().collect(),
+        done: Vec::with_capacity(tiles.len()),
+    });
+    let finished =
+        || renderer.cancel.is_cancelled() || queue.lock().unwrap().done.len() == tiles.len();
+
+    // Accept workers until every tile is back, without blocking so that
+    // the loop notices when that happens.
+    listener.set_nonblocking(true)?;
+    thread::scope(|scope| {
+        while !finished() {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    stream.set_nonblocking(false)?;
+                    let queue = &queue;
+                    scope.spawn(move || {
+                        let served =
+                            serve(stream, job, renderer, queue, tiles.len(), target, tracker);
+                        if let Err(e) = served {
+                            eprintln!("lost worker {}: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(queue.into_inner().unwrap().done)
+}
+
+/// Connect to a coordinator, returning the connection along with the
+/// settings of the job to build the renderer from.
+pub fn join(address: impl ToSocketAddrs) -> io::Result<(TcpStream, Vec<String>)> {
+    let stream = TcpStream::connect(address)?;
+    stream.set_nodelay(true)?;
+
+    let mut w = BufWriter::new(&stream);
+    w.write_all(MAGIC)?;
+    put_u32(&mut w, VERSION)?;
+    put_u32(&mut w, rayon::current_num_threads() as u32)?;
+    w.flush()?;
+    drop(w);
+
+    // Read unbuffered, leaving the tiles that may follow to `work`.
+    let mut r = &stream;
+    let count = get_u32(&mut r)?;
+    let job = (0..count)
+        .map(|_| get_str(&mut r))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok((stream, job))
+}
+
+/// Render the tiles sent by the coordinator until it runs out of them.
+pub fn work(stream: TcpStream, renderer: &Renderer) -> io::Result<()> {
+    let mut r = BufReader::new(&stream);
+    let mut w = BufWriter::new(&stream);
+
+    loop {
+        match get_u32(&mut r)? {
+            TILES => {
+                let target = get_u32(&mut r)?;
+                let count = get_u32(&mut r)?;
+                let tiles = (0..count)
+                    .map(|_| get_tile(&mut r))
+                    .collect::<io::Result<Vec<_>>>()?;
+
+                let mut rendered = renderer.start(&tiles);
+                renderer.refine(&mut rendered, target, None, &Tracker::new(&(), 0, 0));
+
+                put_u32(&mut w, rendered.len() as u32)?;
+                for rendered in &rendered {
+                    write_tile(&mut w, rendered)?;
+                }
+                put_stats(
+                    &mut w,
+                    &std::mem::take(&mut *renderer.stats.lock().unwrap()),
+                )?;
+                w.flush()?;
+            }
+            DONE => return Ok(()),
+            tag => return Err(invalid(format!("unknown message {}", tag))),
+        }
+    }
+}
+
+// Hand out batches of tiles to a worker until every tile is back.
+fn serve(
+    stream: TcpStream,
+    job: &[String],
+    renderer: &Renderer,
+    queue: &Mutex<Queue>,
+    total: usize,
+    target: u32,
+    tracker: &Tracker<impl Observer>,
+) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut r = BufReader::new(&stream);
+    let mut w = BufWriter::new(&stream);
+
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC || get_u32(&mut r)? != VERSION {
+        return Err(invalid("not a worker of this version".to_owned()));
+    }
+    let threads = get_u32(&mut r)?.max(1) as usize;
+    // Workers may take as long as they need to render their tiles.
+    stream.set_read_timeout(None)?;
+
+    put_u32(&mut w, job.len() as u32)?;
+    for arg in job {
+        put_str(&mut w, arg)?;
+    }
+    w.flush()?;
+
+    loop {
+        let batch = {
+            let mut queue = queue.lock().unwrap();
+            if renderer.cancel.is_cancelled() || queue.done.len() == total {
+                break;
+            }
+            let count = threads.min(queue.pending.len());
+            queue.pending.drain(..count).collect::<Vec<_>>()
+        };
+        // Other workers hold the last tiles, which come back here if
+        // they fail.
+        if batch.is_empty() {
+            thread::sleep(POLL);
+            continue;
+        }
+
+        match exchange(&mut r, &mut w, renderer, &batch, target) {
+            Ok(rendered) => {
+                for rendered in &rendered {
+                    let pixels_done = renderer.pixels_done(rendered);
+                    tracker.update(rendered.tile.index, pixels_done, rendered.samples());
+                }
+                queue.lock().unwrap().done.extend(rendered);
+            }
+            Err(e) => {
+                let mut queue = queue.lock().unwrap();
+                for tile in batch.into_iter().rev() {
+                    queue.pending.push_front(tile);
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    put_u32(&mut w, DONE)?;
+    w.flush()
+}
+
+// Send a batch of tiles to a worker and wait for them to come back.
+fn exchange(
+    r: &mut impl Read,
+    w: &mut impl Write,
+    renderer: &Renderer,
+    batch: &[Tile],
+    target: u32,
+) -> io::Result<Vec<RenderedTile>> {
+    put_u32(w, TILES)?;
+    put_u32(w, target)?;
+    put_u32(w, batch.len() as u32)?;
+    for tile in batch {
+        put_tile(w, tile)?;
+    }
+    w.flush()?;
+
+    if get_u32(r)? as usize != batch.len() {
+        return Err(invalid(
+            "worker returned the wrong number of tiles".to_owned(),
+        ));
+    }
+    let rendered = batch
+        .iter()
+        .map(|tile| {
+            // Only trust the size of the tiles sent.
+            let returned = get_tile(r)?;
+            if &returned != tile {
+                return Err(invalid(format!("worker returned tile {:?}", returned)));
+            }
+            let mut rendered = RenderedTile::new(tile, renderer.splats.around(tile));
+            read_samples(r, &mut rendered)?;
+            Ok(rendered)
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    // Paths end after at most `max_bounce` bounces.
+    let stats = get_stats(r, renderer.max_bounce.max(0) as usize + 1)?;
+    renderer.stats.lock().unwrap().merge(&stats);
+    Ok(rendered)
+}
+
+fn put_stats(w: &mut impl Write, stats: &RayStats) -> io::Result<()> {
+    put_u64(w, stats.camera_rays)?;
+    put_u64(w, stats.secondary_rays)?;
+    put_u64(w, stats.shadow_rays)?;
+    put_u64(w, stats.intersection_tests.spheres)?;
+    put_u32(w, stats.depths.len() as u32)?;
+    for &count in &stats.depths {
+        put_u64(w, count)?;
+    }
+    Ok(())
+}
+
+// Read ray statistics counting paths of up to `max_depths` lengths.
+fn get_stats(r: &mut impl Read, max_depths: usize) -> io::Result<RayStats> {
+    let mut stats = RayStats {
+        camera_rays: get_u64(r)?,
+        secondary_rays: get_u64(r)?,
+        shadow_rays: get_u64(r)?,
+        ..RayStats::default()
+    };
+    stats.intersection_tests.spheres = get_u64(r)?;
+    let depths = get_u32(r)? as usize;
+    if depths > max_depths {
+        return Err(invalid(format!("statistics of {} path lengths", depths)));
+    }
+    stats.depths = (0..depths).map(|_| get_u64(r)).collect::<io::Result<_>>()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::model::Model;
+    use crate::render::tests::with_renderer;
+    use crate::vec3::{vec3, Vec3};
+
+    use std::net::SocketAddr;
+
+    const SAMPLES: u32 = 8;
+
+    fn scene<'mat>(ground: &'mat Material<'mat>, light: &'mat Material<'mat>) -> Model<'mat> {
+        Model::list(vec![
+            Model::sphere(vec3(0.0, -101.0, -3.0), 100.0, ground),
+            Model::sphere(vec3(0.5, 0.5, -3.0), 0.8, light),
+        ])
+    }
+
+    // Join the coordinator at `address` and render the tiles it sends,
+    // one at a time. Returns the job, or `None` if the coordinator was
+    // done before the worker joined.
+    fn worker(address: SocketAddr, world: &Model) -> Option<Vec<String>> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let (stream, job) = join(address).ok()?;
+            with_renderer(world, SAMPLES, |renderer, _| work(stream, renderer)).unwrap();
+            Some(job)
+        })
+    }
+
+    // Render locally, and then have `workers` render the same tiles for a
+    // coordinator, returning both images.
+    fn render_both(
+        world: &Model,
+        workers: impl FnOnce(SocketAddr) + Send,
+    ) -> (Vec<Vec3>, Vec<Vec3>) {
+        with_renderer(world, SAMPLES, |renderer, tiles| {
+            let tracker = Tracker::new(&(), 0, 0);
+            let mut rendered = renderer.start(tiles);
+            renderer.refine(&mut rendered, SAMPLES, None, &tracker);
+            let local = renderer.assemble(&rendered).film;
+
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let job = ["--samples".to_owned(), SAMPLES.to_string()];
+            let rendered = thread::scope(|scope| {
+                scope.spawn(|| workers(address));
+                coordinate(listener, &job, renderer, tiles, SAMPLES, &tracker).unwrap()
+            });
+            assert_eq!(rendered.len(), tiles.len());
+            let distributed = renderer.assemble(&rendered).film;
+            (local.pixels().to_vec(), distributed.pixels().to_vec())
+        })
+    }
+
+    #[test]
+    fn workers_render_the_local_image() {
+        let ground = Material::lambertian(vec3(0.5, 0.5, 0.5));
+        let light = Material::diffuse_light(Vec3::all(4.0));
+        let world = scene(&ground, &light);
+
+        let (local, distributed) = render_both(&world, |address| {
+            thread::scope(|scope| {
+                let workers = [(); 2].map(|_| scope.spawn(|| worker(address, &world)));
+                for worker in workers {
+                    if let Some(job) = worker.join().unwrap() {
+                        assert_eq!(job, ["--samples", "8"]);
+                    }
+                }
+            })
+        });
+        assert!(local.iter().any(|&pixel| pixel != Vec3::ZERO));
+        assert_eq!(local, distributed);
+    }
+
+    // A worker claiming to return a tile larger than the one it was sent
+    // is dropped, and its tile rendered by another.
+    #[test]
+    fn worker_returning_another_tile_is_dropped() {
+        let ground = Material::lambertian(vec3(0.5, 0.5, 0.5));
+        let light = Material::diffuse_light(Vec3::all(4.0));
+        let world = scene(&ground, &light);
+
+        let (local, distributed) = render_both(&world, |address| {
+            let stream = TcpStream::connect(address).unwrap();
+            let (mut r, mut w) = (&stream, &stream);
+            w.write_all(MAGIC).unwrap();
+            put_u32(&mut w, VERSION).unwrap();
+            put_u32(&mut w, 1).unwrap();
+            for _ in 0..get_u32(&mut r).unwrap() {
+                get_str(&mut r).unwrap();
+            }
+
+            assert_eq!(get_u32(&mut r).unwrap(), TILES);
+            assert_eq!(get_u32(&mut r).unwrap(), SAMPLES);
+            assert_eq!(get_u32(&mut r).unwrap(), 1);
+            let tile = get_tile(&mut r).unwrap();
+            put_u32(&mut w, 1).unwrap();
+            let huge = Tile {
+                x: 0..u32::MAX,
+                y: 0..u32::MAX,
+                ..tile
+            };
+            put_tile(&mut w, &huge).unwrap();
+            assert!(matches!(r.read(&mut [0]), Ok(0) | Err(_)));
+
+            worker(address, &world).unwrap();
+        });
+        assert_eq!(local, distributed);
+    }
+
+    // A connection that never introduces itself is dropped in time for
+    // the coordinator to return once the tiles are back.
+    #[test]
+    fn silent_connection_times_out() {
+        let ground = Material::lambertian(vec3(0.5, 0.5, 0.5));
+        let light = Material::diffuse_light(Vec3::all(4.0));
+        let world = scene(&ground, &light);
+
+        // Kept open until the coordinator is done.
+        let (keep, kept) = std::sync::mpsc::channel();
+        let (local, distributed) = render_both(&world, |address| {
+            keep.send(TcpStream::connect(address).unwrap()).unwrap();
+            worker(address, &world).unwrap();
+        });
+        assert_eq!(local, distributed);
+        drop(kept);
+    }
+}
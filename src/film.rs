@@ -0,0 +1,259 @@
+use crate::filter::Filter;
+use crate::output::{self, Channel, PixelType};
+use crate::tile::Tile;
+use crate::tonemap::DisplayTransform;
+use crate::vec3::Vec3;
+
+use image::hdr::HDREncoder;
+use image::{ImageBuffer, Rgb, RgbImage};
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::ops::Range;
+use std::path::Path;
+
+/// Linear radiance of every pixel of the rendered image, stored in
+/// row-major order starting at the top row.
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+}
+
+impl Film {
+    /// Construct a black film of the given dimensions.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Vec3::ZERO; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, radiance: Vec3) {
+        let index = self.index(x, y);
+        self.pixels[index] = radiance;
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    /// Convert the film to a displayable 8-bit sRGB image.
+    pub fn to_rgb_image(&self, display: &DisplayTransform) -> RgbImage {
+        ImageBuffer::from_fn(self.width, self.height, |x, y| {
+            let col = 255.99 * display.apply(self.get(x, y));
+            Rgb([col.x as u8, col.y as u8, col.z as u8])
+        })
+    }
+
+    /// Split the film into its red, green and blue channels.
+    pub fn channels(&self) -> [Vec<f32>; 3] {
+        [
+            self.pixels.iter().map(|p| p.x).collect(),
+            self.pixels.iter().map(|p| p.y).collect(),
+            self.pixels.iter().map(|p| p.z).collect(),
+        ]
+    }
+
+    /// Save the film to a file, picking the format from its extension.
+    ///
+    /// `exr`, `hdr` and `pfm` files keep the linear float radiance, with
+    /// `exr` channels stored as `pixel_type`, and any other format goes
+    /// through the display transform.
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        display: &DisplayTransform,
+        pixel_type: PixelType,
+    ) -> io::Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("exr") => self.save_exr(path, pixel_type),
+            Some("hdr") => self.save_hdr(path),
+            Some("pfm") => self.save_pfm(path),
+            _ => self.to_rgb_image(display).save(path),
+        }
+    }
+
+    /// Save the film as an OpenEXR image with `R`, `G` and `B` channels.
+    pub fn save_exr(&self, path: impl AsRef<Path>, pixel_type: PixelType) -> io::Result<()> {
+        let [r, g, b] = self.channels();
+        output::write_exr(
+            BufWriter::new(File::create(path)?),
+            self.width,
+            self.height,
+            &[
+                Channel::new("R", &r),
+                Channel::new("G", &g),
+                Channel::new("B", &b),
+            ],
+            pixel_type,
+        )
+    }
+
+    /// Save the film as a Radiance RGBE image.
+    pub fn save_hdr(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let data = self
+            .pixels
+            .iter()
+            .map(|p| Rgb([p.x, p.y, p.z]))
+            .collect::<Vec<_>>();
+        HDREncoder::new(BufWriter::new(File::create(path)?)).encode(
+            &data,
+            self.width as usize,
+            self.height as usize,
+        )
+    }
+
+    /// Save the film as a colour Portable Float Map.
+    pub fn save_pfm(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let data = self
+            .pixels
+            .iter()
+            .flat_map(|p| vec![p.x, p.y, p.z])
+            .collect::<Vec<_>>();
+        output::write_pfm(
+            BufWriter::new(File::create(path)?),
+            self.width,
+            self.height,
+            &data,
+        )
+    }
+}
+
+/// Film accumulating samples weighted by a reconstruction filter, so that
+/// every sample contributes to all of the pixels within the filter radius.
+///
+/// It may cover a region of the image only, to gather the samples of a
+/// part of the image before merging them into the whole.
+#[derive(Debug, Clone)]
+pub struct SplatFilm {
+    filter: Filter,
+    radius: f32,
+    // Pixels covered by the film, in image coordinates.
+    pub(crate) x: Range<u32>,
+    pub(crate) y: Range<u32>,
+    pub(crate) weighted: Vec<Vec3>,
+    pub(crate) weights: Vec<f32>,
+}
+
+impl SplatFilm {
+    /// Construct an empty film covering a whole image.
+    pub fn new(width: u32, height: u32, filter: Filter, radius: f32) -> Self {
+        Self::covering(filter, radius, 0..width, 0..height)
+    }
+
+    fn covering(filter: Filter, radius: f32, x: Range<u32>, y: Range<u32>) -> Self {
+        let size = x.len() * y.len();
+        Self {
+            filter,
+            radius,
+            x,
+            y,
+            weighted: vec![Vec3::ZERO; size],
+            weights: vec![0.0; size],
+        }
+    }
+
+    /// Returns an empty film with the same filter, covering the part of
+    /// this one within the given ranges of pixels.
+    pub fn region(&self, x: Range<u32>, y: Range<u32>) -> Self {
+        let clip = |r: Range<u32>, bounds: &Range<u32>| {
+            let start = r.start.clamp(bounds.start, bounds.end);
+            start..r.end.clamp(start, bounds.end)
+        };
+        Self::covering(self.filter, self.radius, clip(x, &self.x), clip(y, &self.y))
+    }
+
+    /// Returns an empty film covering the pixels a tile may splat its
+    /// samples on, the tile and a margin around it.
+    pub fn around(&self, tile: &Tile) -> Self {
+        let margin = self.margin();
+        self.region(
+            tile.x.start.saturating_sub(margin)..tile.x.end + margin,
+            tile.y.start.saturating_sub(margin)..tile.y.end + margin,
+        )
+    }
+
+    /// Number of pixels on each side of a pixel that its samples may
+    /// contribute to.
+    pub fn margin(&self) -> u32 {
+        (self.radius - 0.5).ceil().max(0.0) as u32
+    }
+
+    /// Add a sample at a continuous position on the image, where pixel
+    /// `(x, y)` spans `[x, x + 1) × [y, y + 1)`.
+    pub fn add_sample(&mut self, (px, py): (f32, f32), radiance: Vec3) {
+        let radius = self.radius;
+        let range = |p: f32, bounds: &Range<u32>| {
+            let start = (p - 0.5 - radius).ceil().max(bounds.start as f32) as u32;
+            let end = ((p - 0.5 + radius).floor() + 1.0).min(bounds.end as f32);
+            start..(end.max(start as f32) as u32)
+        };
+        let (xs, ys) = (range(px, &self.x), range(py, &self.y));
+
+        for y in ys {
+            for x in xs.clone() {
+                let offset = (px - (x as f32 + 0.5), py - (y as f32 + 0.5));
+                let weight = self.filter.evaluate(offset, self.radius);
+                if weight != 0.0 {
+                    let index = self.index(x, y);
+                    self.weighted[index] += radiance * weight;
+                    self.weights[index] += weight;
+                }
+            }
+        }
+    }
+
+    /// Add the samples of another film, over the pixels both cover.
+    pub fn merge(&mut self, other: &SplatFilm) {
+        for y in other.y.start.max(self.y.start)..other.y.end.min(self.y.end) {
+            for x in other.x.start.max(self.x.start)..other.x.end.min(self.x.end) {
+                let (from, to) = (other.index(x, y), self.index(x, y));
+                self.weighted[to] += other.weighted[from];
+                self.weights[to] += other.weights[from];
+            }
+        }
+    }
+
+    /// Normalise the weighted samples into a film of the covered pixels.
+    pub fn to_film(&self) -> Film {
+        let mut film = Film::new(self.x.len() as u32, self.y.len() as u32);
+        for (pixel, (weighted, weight)) in film
+            .pixels
+            .iter_mut()
+            .zip(self.weighted.iter().zip(&self.weights))
+        {
+            if *weight > 0.0 {
+                *pixel = *weighted / *weight;
+            }
+        }
+        film
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((y - self.y.start) as usize) * self.x.len() + (x - self.x.start) as usize
+    }
+}
@@ -0,0 +1,114 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/// Pixel reconstruction filter, weighting every sample by its distance
+/// to the centre of each pixel it contributes to.
+///
+/// All filters are separable and stretched to the radius they are
+/// evaluated with.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Filter {
+    /// Equal weight everywhere, which with a radius of half a pixel
+    /// averages the samples of each pixel on their own.
+    #[default]
+    Box,
+    /// Weight falling linearly to zero at the radius.
+    Tent,
+    /// Gaussian with a standard deviation of a third of the radius,
+    /// shifted down to reach zero at the radius.
+    Gaussian,
+    /// Mitchell-Netravali cubic with `B = C = 1/3`.
+    Mitchell,
+    /// Sinc windowed by a wider sinc, with as many lobes as the radius.
+    Lanczos,
+}
+
+impl Filter {
+    /// Name of the filter, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Filter::Box => "box",
+            Filter::Tent => "tent",
+            Filter::Gaussian => "gaussian",
+            Filter::Mitchell => "mitchell",
+            Filter::Lanczos => "lanczos",
+        }
+    }
+
+    /// Radius the filter is normally used with, in pixels.
+    pub fn default_radius(self) -> f32 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0,
+            Filter::Lanczos => 3.0,
+        }
+    }
+
+    /// Weight of a sample at the given offset from the centre of a pixel,
+    /// in pixels. Mitchell and Lanczos weights may be negative.
+    pub fn evaluate(self, (x, y): (f32, f32), radius: f32) -> f32 {
+        self.evaluate_1d(x, radius) * self.evaluate_1d(y, radius)
+    }
+
+    fn evaluate_1d(self, x: f32, radius: f32) -> f32 {
+        let x = x.abs();
+        if x >= radius {
+            return 0.0;
+        }
+
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => 1.0 - x / radius,
+            Filter::Gaussian => {
+                let sigma = radius / 3.0;
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                gaussian(x) - gaussian(radius)
+            }
+            Filter::Mitchell => mitchell(2.0 * x / radius, 1.0 / 3.0, 1.0 / 3.0),
+            Filter::Lanczos => sinc(x) * sinc(x / radius),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "box" => Ok(Filter::Box),
+            "tent" | "triangle" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            "lanczos" => Ok(Filter::Lanczos),
+            _ => Err(format!("unknown filter `{}`", s)),
+        }
+    }
+}
+
+// Mitchell-Netravali cubic, with a support of `[-2, 2]`.
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b)
+    } else if x < 2.0 {
+        (-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+    value / 6.0
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
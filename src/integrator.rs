@@ -0,0 +1,131 @@
+use crate::aov::{Aovs, MaterialIds};
+use crate::model::Model;
+use crate::onb::{self, Onb};
+use crate::ray::Ray;
+use crate::render::{trace, trace_shadow};
+use crate::sampler::Sampler;
+use crate::stats::RayStats;
+use crate::vec3::Vec3;
+
+use std::str::FromStr;
+
+/// Number of squares of the UV checker along each texture axis.
+const CHECKER_SQUARES: f32 = 8.0;
+
+/// Way the radiance of a camera ray is estimated.
+///
+/// Apart from the path tracer, integrators only look at the first hit of
+/// the camera ray, to inspect a scene far faster than rendering it. They
+/// record no bounces when tracing pixels.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Integrator {
+    /// Full path tracing, the beauty render.
+    #[default]
+    Path,
+    /// Shading normal, mapped from `[-1, 1]` to `[0, 1]`.
+    Normals,
+    /// Distance to the first hit, divided by the largest one in the image
+    /// once it is assembled. Black where nothing is hit.
+    Depth,
+    /// Albedo of the material, without any lighting.
+    Albedo,
+    /// Checkerboard over the texture coordinates, tinted by them.
+    UvChecker,
+    /// Cosine of the angle between the ray and the shading normal.
+    FacingRatio,
+    /// Fraction of the hemisphere around the shading normal left open
+    /// within `distance` of the hit, one cosine weighted ray per sample.
+    AmbientOcclusion { distance: f32 },
+}
+
+impl Integrator {
+    /// Name of the integrator, as parsed by `from_str`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Integrator::Path => "path",
+            Integrator::Normals => "normals",
+            Integrator::Depth => "depth",
+            Integrator::Albedo => "albedo",
+            Integrator::UvChecker => "uv-checker",
+            Integrator::FacingRatio => "facing-ratio",
+            Integrator::AmbientOcclusion { .. } => "ambient-occlusion",
+        }
+    }
+
+    /// Shade the first hit of a camera ray. Recorded in the direct light
+    /// of the returned AOVs, next to the surface details of the hit.
+    ///
+    /// Must not be called for the path tracer.
+    pub(crate) fn shade(
+        self,
+        ray: Ray,
+        world: &Model,
+        ids: &MaterialIds,
+        sampler: &mut impl Sampler,
+        stats: &mut RayStats,
+    ) -> Aovs {
+        let mut aovs = Aovs::default();
+        let rec = match trace(world, &ray, 0, stats) {
+            Some(rec) => rec,
+            None => {
+                stats.end_path(0);
+                return aovs;
+            }
+        };
+        aovs.record_first_hit(&ray, &rec, ids);
+
+        // Face the normal towards the camera, so that the insides of
+        // spheres look like their outsides.
+        let normal = if rec.shading_normal.dot(ray.direction) > 0.0 {
+            -rec.shading_normal
+        } else {
+            rec.shading_normal
+        };
+
+        aovs.direct = match self {
+            Integrator::Path => unreachable!("the path tracer has no debug shading"),
+            Integrator::Normals => (rec.shading_normal + Vec3::ONE) * 0.5,
+            Integrator::Depth => Vec3::all(aovs.depth),
+            Integrator::Albedo => aovs.albedo,
+            Integrator::UvChecker => {
+                let (u, v) = rec.uv;
+                let square = (u * CHECKER_SQUARES).floor() + (v * CHECKER_SQUARES).floor();
+                let shade = if square.rem_euclid(2.0) < 1.0 {
+                    1.0
+                } else {
+                    0.25
+                };
+                Vec3::new(u, v, 1.0 - u) * shade
+            }
+            Integrator::FacingRatio => Vec3::all(-normal.dot(ray.direction.normalize())),
+            Integrator::AmbientOcclusion { distance } => {
+                let (u1, u2) = sampler.next_2d();
+                let direction = Onb::from_w(normal).to_world(onb::cosine_direction(u1, u2));
+                let occluder = trace_shadow(world, &rec.spawn_ray(direction), stats);
+                match occluder {
+                    Some(occluder) if occluder.parameter * direction.mag() < distance => Vec3::ZERO,
+                    _ => Vec3::ONE,
+                }
+            }
+        };
+        stats.end_path(0);
+        aovs
+    }
+}
+
+impl FromStr for Integrator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "path" => Ok(Integrator::Path),
+            "normals" => Ok(Integrator::Normals),
+            "depth" => Ok(Integrator::Depth),
+            "albedo" => Ok(Integrator::Albedo),
+            "uv-checker" | "uv" => Ok(Integrator::UvChecker),
+            "facing-ratio" => Ok(Integrator::FacingRatio),
+            "ambient-occlusion" | "ao" => Ok(Integrator::AmbientOcclusion { distance: 1.0 }),
+            _ => Err(format!("unknown integrator `{}`", s)),
+        }
+    }
+}
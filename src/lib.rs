@@ -0,0 +1,25 @@
+pub mod adaptive;
+pub mod aov;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod debug;
+pub mod denoise;
+pub mod distributed;
+pub mod film;
+pub mod filter;
+pub mod integrator;
+pub mod material;
+pub mod model;
+pub mod onb;
+pub mod output;
+pub mod progress;
+pub mod ray;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod stats;
+pub mod texture;
+pub mod tile;
+pub mod tonemap;
+pub mod vec3;
@@ -1,140 +1,413 @@
-mod camera;
-mod material;
-mod model;
-mod ray;
-mod vec3;
+mod options;
+mod server;
 
-use camera::Camera;
-use material::{Material, Scatter};
-use model::Model;
-use ray::Ray;
-use vec3::{vec3, Vec3};
+use options::Options;
 
-use image::{ImageBuffer, Rgb, RgbImage};
+use raytracer::adaptive::AdaptiveSampling;
+use raytracer::aov::MaterialIds;
+use raytracer::camera::Camera;
+use raytracer::checkpoint;
+use raytracer::color;
+use raytracer::debug;
+use raytracer::denoise::Denoiser;
+use raytracer::distributed;
+use raytracer::film::{Film, SplatFilm};
+use raytracer::material::Material;
+use raytracer::model::Model;
+use raytracer::output::PixelType;
+use raytracer::progress::{CancelToken, Observer, Progress, Tracker};
+use raytracer::render::{self, Cost, Frame, Renderer};
+use raytracer::sampler::SamplerKind;
+use raytracer::scene::Scene;
+use raytracer::stats::RenderStats;
+use raytracer::tile;
+use raytracer::vec3::vec3;
 
 use indicatif::{ProgressBar, ProgressStyle};
 
-use rayon::prelude::*;
-
+use std::fs;
 use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Samples per pixel between checkpoints and timed snapshots.
+const CHECKPOINT_STEP: u32 = 16;
 
 fn main() -> io::Result<()> {
-    // Construct the scene.
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mut options = Options::from_args(args.iter().cloned())?;
+
+    // A server renders the jobs posted to it instead.
+    if let Some(address) = &options.serve {
+        let dir = options.output.parent().unwrap_or_else(|| Path::new("."));
+        return server::serve(address, dir);
+    }
+
+    // Workers render with the settings of their coordinator.
+    let worker = match options.worker.take() {
+        Some(address) => {
+            let (stream, job) = distributed::join(address)?;
+            options = Options::from_args(job.into_iter())?;
+            Some(stream)
+        }
+        None => None,
+    };
+
+    // Rendering progress bar stuff.
+    let total_size = options.width as u64 * options.height as u64 * options.samples as u64;
+    let pb = match worker {
+        Some(_) => ProgressBar::hidden(),
+        None => ProgressBar::new(total_size),
+    };
+    pb.set_style(ProgressStyle::default_bar()
+        .template("Rendering {spinner:.green} [{elapsed_precise}] {percent:>3}% [{bar:40.cyan/blue}] {pos}/{len} samples ({per_sec} | {eta})")
+        .progress_chars("#>-"));
+
+    let bar = Bar(pb);
+    run(
+        &options,
+        &Options::job(&args),
+        worker,
+        &bar,
+        CancelToken::new(),
+    )?;
+    bar.0.finish_at_current_pos();
+    Ok(())
+}
+
+// Shows the samples taken on the progress bar.
+struct Bar(ProgressBar);
+
+impl Observer for Bar {
+    fn progress(&self, progress: &Progress) {
+        self.0.set_position(progress.samples_done);
+    }
+}
+
+/// Render the scene with the given options, writing the images to their
+/// output and reporting progress to the observer, until done or
+/// cancelled.
+///
+/// `job` holds the options for workers, if rendering as a coordinator,
+/// and `worker` the connection to a coordinator to render tiles for.
+pub fn run(
+    options: &Options,
+    job: &[String],
+    worker: Option<TcpStream>,
+    observer: &impl Observer,
+    cancel: CancelToken,
+) -> io::Result<()> {
+    let mut stats = RenderStats::new();
+
+    // Construct the scene, the built-in one unless described in a file.
+    let scene = match &options.scene {
+        Some(path) => Some(read_scene(path)?),
+        None => None,
+    };
+    let view = scene.as_ref().map(|scene| scene.view).unwrap_or_default();
     let mat_1 = Material::lambertian(vec3(0.1, 0.2, 0.5));
     let mat_2 = Material::lambertian(vec3(0.8, 0.8, 0.0));
     let mat_3 = Material::metal(vec3(0.8, 0.6, 0.2), 0.0);
     let mat_4 = Material::dielectric(1.5);
     let mat_5 = Material::diffuse_light(vec3(1.0, 0.9, 0.4));
     let mat_6 = Material::Combined { scatterer: &mat_3, emitter: &mat_5 };
-    let world = Model::list(vec![
-        Model::sphere(vec3(0.0, -0.3, -1.0), 0.2, &mat_1),
-        Model::sphere(vec3(0.0, -100.5, -1.0), 100.0, &mat_2),
-        Model::sphere(vec3(1.0, 0.0, -1.0), 0.5, &mat_3),
-        Model::sphere(vec3(0.0, 0.0, -2.0), 0.5, &mat_4),
-        Model::sphere(vec3(0.0, 0.0, -2.0), -0.4, &mat_4),
-        Model::sphere(vec3(-1.0, 0.0, -1.0), 0.40, &mat_6),
-        Model::sphere(vec3(-1.0, 5.0, -1.0), 0.40, &mat_5),
-    ]);
+    let world = match &scene {
+        Some(scene) => scene.model(),
+        None => Model::list(vec![
+            Model::sphere(vec3(0.0, -0.3, -1.0), 0.2, &mat_1),
+            Model::sphere(vec3(0.0, -100.5, -1.0), 100.0, &mat_2),
+            Model::sphere(vec3(1.0, 0.0, -1.0), 0.5, &mat_3),
+            Model::sphere(vec3(0.0, 0.0, -2.0), 0.5, &mat_4),
+            Model::sphere(vec3(0.0, 0.0, -2.0), -0.4, &mat_4),
+            Model::sphere(vec3(-1.0, 0.0, -1.0), 0.40, &mat_6),
+            Model::sphere(vec3(-1.0, 5.0, -1.0), 0.40, &mat_5),
+        ]),
+    };
 
     // Image parameters.
-    let nx = 900u32;
-    let ny = 600u32;
-    let ns = 1000u32;
+    let nx = options.width;
+    let ny = options.height;
+    let ns = options.samples;
 
-    // Rendering progress bar stuff.
-    let total_size = nx * ny;
-    let pb = ProgressBar::new(total_size.into());
-    pb.set_style(ProgressStyle::default_bar()
-        .template("Rendering {spinner:.green} [{elapsed_precise}] {percent:>3}% [{bar:40.cyan/blue}] {pos}/{len} pixels ({per_sec} | {eta})")
-        .progress_chars("#>-"));
+    // Progressive renders go over the image once per pass.
+    let passes = if options.progressive {
+        render::progressive_passes(ns)
+    } else {
+        vec![ns]
+    };
 
     // Setting up the camera.
-    let look_from = vec3(-3.0, 3.0, 2.0);
-    let look_at = vec3(0.0, 0.0, -1.0);
-    let dist_to_focus = (look_from - look_at).mag();
-    let aperture = 0.0;
+    let dist_to_focus = (view.look_from - view.look_at).mag();
     let camera = Camera::new(
-        look_from,
-        look_at,
+        view.look_from,
+        view.look_at,
         vec3(0.0, 1.0, 0.0),
-        20.0,
+        view.vfov,
         nx as f32 / ny as f32,
-        aperture,
+        view.aperture,
         dist_to_focus,
     );
 
-    let mut buf: RgbImage = ImageBuffer::new(nx, ny);
-
-    (0..ny)
-        .into_par_iter()
-        .flat_map(|j| {
-            (0..nx)
-                .into_par_iter()
-                .map_with(j, |&mut j, i| {
-                    let mut col = (0..ns)
-                        .into_par_iter()
-                        .map(|_| {
-                            (
-                                (i as f32 + rand::random::<f32>()) / (nx as f32),
-                                (j as f32 + rand::random::<f32>()) / (ny as f32),
-                            )
-                        })
-                        .map(|(u, v)| camera.get_ray(u, v))
-                        .map(|ray| color(ray, &world, 50))
-                        .reduce(|| Vec3::ZERO, |a, b| a + b);
-                    col = 255.99
-                        * (col / (ns as f32))
-                            .map(f32::sqrt)
-                            .map(|f| f.max(0.0).min(1.0));
-                    (i, j, Rgb([col.x as u8, col.y as u8, col.z as u8]))
-                })
-                .inspect(|_| pb.inc(1))
-        })
-        .collect::<Vec<_>>()
-        .into_iter()
-        .for_each(|(x, y, pixel)| buf.put_pixel(x, y, pixel));
-
-    buf.save("./output/default.png")?;
+    let ids = MaterialIds::new(&world);
+    let sampling = match options.adaptive {
+        Some(threshold) => AdaptiveSampling::new(options.min_samples, ns, threshold),
+        None => AdaptiveSampling::fixed(ns),
+    };
 
-    Ok(())
-}
+    let mut renderer = Renderer {
+        camera: &camera,
+        world: &world,
+        ids: &ids,
+        width: nx,
+        height: ny,
+        sampler: options.sampler(),
+        sampling,
+        splats: SplatFilm::new(nx, ny, options.filter, options.filter_radius()),
+        max_bounce: 50,
+        roulette_depth: options.roulette_depth,
+        clamp_direct: options.clamp_direct,
+        clamp_indirect: options.clamp_indirect,
+        regularization: options.regularization,
+        integrator: options.integrator(),
+        cancel,
+        stats: Default::default(),
+    };
+    let mut settings = checkpoint::Settings {
+        width: nx,
+        height: ny,
+        seed: options.seed,
+        sampler: options.sampler.clone(),
+        strata: ns,
+        filter: options.filter,
+        radius: options.filter_radius(),
+        integrator: options.integrator(),
+        roulette_depth: options.roulette_depth,
+        clamp_direct: options.clamp_direct,
+        clamp_indirect: options.clamp_indirect,
+        regularization: options.regularization,
+    };
+
+    if let Some(stream) = worker {
+        return distributed::work(stream, &renderer);
+    }
+
+    // Log the paths behind a pixel instead of rendering the image.
+    if let Some(pixel) = options.trace_pixel {
+        if pixel.0 >= nx || pixel.1 >= ny {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("pixel {:?} is outside of the image", pixel),
+            ));
+        }
+        let samples = options.trace_samples.clone().unwrap_or(0..ns);
+        let paths = renderer.trace_pixel(pixel, samples);
+        for path in &paths {
+            print!("{}", path);
+        }
+        if let Some(obj) = &options.trace_obj {
+            debug::save_obj(obj, &paths)?;
+        }
+        return Ok(());
+    }
+    stats.lap("setup");
+    let tracker = Tracker::new(
+        observer,
+        nx as u64 * ny as u64,
+        nx as u64 * ny as u64 * ns as u64,
+    );
 
-fn color(mut ray: Ray, world: &Model, max_bounce: i32) -> Vec3 {
-    let mut factor = Vec3::ONE;
-    let mut emit = Vec3::ZERO;
-    let mut bounces = 0;
+    // Pick up where a previous render left off, if asked to, or have the
+    // workers of a coordinator render every tile at once. `reached` is the
+    // number of samples every pixel has been refined up to.
+    let tiles = tile::tiles(nx, ny, options.tile_size, options.tile_order);
+    let (mut reached, mut rendered) = match (&options.coordinator, &options.checkpoint) {
+        (Some(address), _) => {
+            let listener = TcpListener::bind(address)?;
+            let rendered = distributed::coordinate(listener, job, &renderer, &tiles, ns, &tracker)?;
+            (ns, rendered)
+        }
+        (None, Some(path)) if options.resume => {
+            let (reached, rendered) = checkpoint::load(path, &mut settings)?;
+            // Carry on with the strata the checkpoint started with.
+            renderer.sampler =
+                SamplerKind::from_name(&settings.sampler, settings.strata, settings.seed)
+                    .expect("sampler name is validated");
+            for rendered in &rendered {
+                let pixels_done = renderer.pixels_done(rendered);
+                tracker.update(rendered.tile.index, pixels_done, rendered.samples());
+            }
+            (reached, rendered)
+        }
+        _ => (0, renderer.start(&tiles)),
+    };
+
+    let display = options.display();
+    let start = Instant::now();
+    let deadline = options
+        .time_limit
+        .map(|seconds| start + Duration::from_secs_f32(seconds));
+    let stopped = || {
+        renderer.cancel.is_cancelled()
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    };
+    let mut last_snapshot = start;
+    let mut last_checkpoint = start;
+
+    for &target in &passes {
+        if target <= reached {
+            continue;
+        }
+
+        // With checkpoints or timed snapshots, passes are refined a few
+        // samples at a time so that progress is saved while they run.
+        let mut taken = 0;
+        while reached < target && !stopped() {
+            let step = match (&options.checkpoint, options.snapshot_interval) {
+                (None, None) => target,
+                _ => (reached + CHECKPOINT_STEP).min(target),
+            };
+            taken += renderer.refine(&mut rendered, step, deadline, &tracker);
+            if stopped() {
+                break;
+            }
+            reached = step;
+
+            if let Some(path) = &options.checkpoint {
+                if last_checkpoint.elapsed().as_secs_f32() >= options.checkpoint_interval {
+                    checkpoint::save(path, &settings, reached, &rendered)?;
+                    last_checkpoint = Instant::now();
+                }
+            }
+            if let Some(seconds) = options.snapshot_interval {
+                if reached < ns && last_snapshot.elapsed().as_secs_f32() >= seconds {
+                    let frame = renderer.assemble(&rendered);
+                    tracker.preview(&frame.film);
+                    frame
+                        .film
+                        .save(&options.output, &display, options.exr_precision)?;
+                    last_snapshot = Instant::now();
+                }
+            }
+        }
 
-    while let Some(rec) = world.hit(&ray, 0.00001, std::f32::MAX) {
-        // Maximum number of bounces. If exceeded, return the
-        // result of all interactions so far with the scene.
-        if bounces >= max_bounce {
+        // Stop early once out of time or cancelled, or once every pixel
+        // has converged below the noise threshold.
+        if target == ns || stopped() || taken == 0 {
             break;
         }
 
-        // Get the scattering result from interacting with
-        // the material of the object.
-        let Scatter {
-            scattered,
-            attenuation,
-        } = rec.material.scatter(ray, &rec);
-
-        // If the ray is completely absorbed, then the only
-        // light that could possibly reach the camera is what the
-        // material emits.
-        if scattered == Ray::ZERO || attenuation == Vec3::ZERO {
-            return factor * rec.material.emit(rec);
+        // Without an interval, snapshot after every pass.
+        let frame = renderer.assemble(&rendered);
+        tracker.preview(&frame.film);
+        if options.snapshot_interval.is_none() {
+            frame
+                .film
+                .save(&options.output, &display, options.exr_precision)?;
+        }
+    }
+
+    if let Some(path) = &options.checkpoint {
+        checkpoint::save(path, &settings, reached, &rendered)?;
+    }
+    stats.lap("render");
+
+    let Frame {
+        mut film,
+        aovs: aov_film,
+        sample_counts,
+        time,
+        intersection_tests,
+    } = renderer.assemble(&rendered);
+
+    if let Some(iterations) = options.denoise {
+        let denoiser = Denoiser {
+            iterations,
+            ..Denoiser::default()
+        };
+        film = denoiser.denoise(&film, &aov_film);
+        stats.lap("denoise");
+    }
+    tracker.preview(&film);
+
+    film.save(&options.output, &display, options.exr_precision)?;
+    if let Some(path) = options.linear_output() {
+        film.save_exr(path, options.exr_precision)?;
+    }
+
+    let dir = options.output.parent().unwrap_or_else(|| Path::new("."));
+    let stem = options
+        .output
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("render");
+
+    // Write the render passes next to the beauty render.
+    if let Some(format) = &options.aovs {
+        if format == "exr" {
+            aov_film.save_exr(dir.join(format!("{}_aovs.exr", stem)), Some(&film))?;
+        } else {
+            aov_film.save_passes(dir, stem, format)?;
         }
+    }
+
+    // Show where the samples went, relative to the busiest pixel.
+    if options.sample_heatmap {
+        let max = sample_counts
+            .pixels()
+            .iter()
+            .fold(1.0f32, |max, count| max.max(count.x));
+        let path = dir.join(format!("{}_samples.png", stem));
+        save_heatmap(&sample_counts, max, path)?;
+    }
+
+    // Show which pixels took the most work, relative to the 99th
+    // percentile so that a few outliers, such as pixels whose thread was
+    // preempted, do not wash out the rest.
+    if let Some(cost) = options.cost_heatmap {
+        let costs = match cost {
+            Cost::Time => &time,
+            Cost::IntersectionTests => &intersection_tests,
+        };
+        let mut sorted = costs.pixels().iter().map(|cost| cost.x).collect::<Vec<_>>();
+        sorted.sort_by(f32::total_cmp);
+        let scale = sorted
+            .get(sorted.len() * 99 / 100)
+            .map_or(0.0, |&cost| cost)
+            .max(f32::MIN_POSITIVE);
+        save_heatmap(costs, scale, dir.join(format!("{}_cost.png", stem)))?;
+    }
+    stats.lap("output");
 
-        ray = scattered;
-        factor *= attenuation;
-        emit += rec.material.emit(rec);
-        bounces += 1;
+    stats.rays = renderer.stats.into_inner().unwrap();
+    if options.stats {
+        eprint!("{}", stats.report());
     }
+    if let Some(path) = &options.stats_json {
+        fs::write(path, stats.to_json())?;
+    }
+
+    Ok(())
+}
 
-    // let unit_direction = ray.direction.unit();
-    // let t = 0.5 * (unit_direction.y + 1.0);
-    // let sky_color = (1.0 - t) * Vec3::ID + t * vec3(0.5, 0.7, 1.0);
-    let sky_color = Vec3::ZERO;
+// Save values held in the first channel of a film as a false colour
+// image, with the hottest colour from `max` up.
+// Read and parse a scene file, naming the file in errors.
+fn read_scene(path: &Path) -> io::Result<Scene> {
+    fs::read_to_string(path)?.parse().map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
 
-    factor * (sky_color + emit)
-}
\ No newline at end of file
+fn save_heatmap(values: &Film, max: f32, path: impl AsRef<Path>) -> io::Result<()> {
+    let mut heatmap = Film::new(values.width(), values.height());
+    for y in 0..values.height() {
+        for x in 0..values.width() {
+            heatmap.set(x, y, color::heatmap(values.get(x, y).x / max));
+        }
+    }
+    heatmap.save(path, &Default::default(), PixelType::Half)
+}
@@ -1,5 +1,9 @@
+use crate::color;
 use crate::model::Hit;
+use crate::onb::{self, Onb};
 use crate::ray::Ray;
+use crate::sampler::{self, Sampler};
+use crate::texture::{BumpMap, NormalMap, Texture};
 use crate::vec3::Vec3;
 
 /// The result of calculations of a ray hitting and bouncing off a
@@ -30,20 +34,50 @@ pub enum Material<'mat> {
     Lambertian(Lambertian),
     Metal(Metal),
     Dielectric(Dielectric),
+    Subsurface(Subsurface),
     DiffuseLight(DiffuseLight),
     Combined {
         scatterer: &'mat Material<'mat>,
         emitter: &'mat Material<'mat>,
     },
+    /// Stochastic blend of two materials, where the weight texture gives
+    /// the probability of picking `second` over `first` at each point.
+    Mix {
+        first: &'mat Material<'mat>,
+        second: &'mat Material<'mat>,
+        weight: Texture,
+    },
+    /// Dielectric clear coat layered over any base material.
+    Coated {
+        base: &'mat Material<'mat>,
+        coat: Coat,
+    },
+    /// Base material shaded with normals from a tangent-space normal map.
+    NormalMapped {
+        base: &'mat Material<'mat>,
+        map: NormalMap,
+    },
+    /// Base material shaded with normals perturbed by a height map.
+    BumpMapped {
+        base: &'mat Material<'mat>,
+        map: BumpMap,
+    },
 }
 
-impl Material<'_> {
+impl<'mat> Material<'mat> {
     /// Convenience method to construct a lambertian reflective
     /// or matte material.
     pub fn lambertian(albedo: Vec3) -> Self {
         Self::Lambertian(Lambertian::new(albedo))
     }
 
+    /// Convenience method to construct a rough diffuse material following
+    /// the Oren-Nayar model, where `roughness` is the standard deviation of
+    /// the microfacet slope angle in radians.
+    pub fn oren_nayar(albedo: Vec3, roughness: f32) -> Self {
+        Self::Lambertian(Lambertian::oren_nayar(albedo, roughness))
+    }
+
     /// Convenience method to construct a reflective or metal material.
     pub fn metal(albedo: Vec3, fuzz: f32) -> Self {
         Self::Metal(Metal::new(albedo, fuzz))
@@ -54,56 +88,286 @@ impl Material<'_> {
         Self::Dielectric(Dielectric::new(ref_idx))
     }
 
+    /// Convenience method to construct a translucent material that scatters
+    /// light beneath its surface, such as skin, wax, marble or milk.
+    ///
+    /// The model this material is applied to must be closed.
+    pub fn subsurface(ref_idx: f32, albedo: Vec3, mean_free_path: f32, anisotropy: f32) -> Self {
+        Self::Subsurface(Subsurface::new(
+            ref_idx,
+            albedo,
+            mean_free_path,
+            anisotropy,
+        ))
+    }
+
+    /// Convenience method to apply a tangent-space normal map to a base
+    /// material.
+    pub fn normal_mapped(base: &'mat Material<'mat>, map: Texture, strength: f32) -> Self {
+        Self::NormalMapped {
+            base,
+            map: NormalMap::new(map, strength),
+        }
+    }
+
+    /// Convenience method to apply a grayscale bump map to a base material.
+    pub fn bump_mapped(base: &'mat Material<'mat>, map: Texture, scale: f32) -> Self {
+        Self::BumpMapped {
+            base,
+            map: BumpMap::new(map, scale),
+        }
+    }
+
     /// Convenience method to construct a diffuse light material.
     pub fn diffuse_light(emittance: Vec3) -> Self {
         Self::DiffuseLight(DiffuseLight::new(emittance))
     }
 
+    /// Convenience method to construct a light material whose emission
+    /// is driven by a texture.
+    pub fn textured_light(emission: Texture, two_sided: bool) -> Self {
+        Self::DiffuseLight(DiffuseLight::textured(emission, two_sided))
+    }
+
+    /// Convenience method to construct a one-sided light of a given tint
+    /// that emits `watts` in total from a surface of the given `area`.
+    pub fn light_from_power(tint: Vec3, watts: f32, area: f32) -> Self {
+        Self::DiffuseLight(DiffuseLight::from_power(tint, watts, area))
+    }
+
+    /// Convenience method to construct a one-sided light with the colour of
+    /// a black body at the given temperature in Kelvin and the given luminance.
+    pub fn blackbody_light(kelvin: f32, luminance: f32) -> Self {
+        Self::DiffuseLight(DiffuseLight::blackbody(kelvin, luminance))
+    }
+
+    /// Convenience method to construct a blend of two materials with
+    /// a constant weight.
+    pub fn mix(first: &'mat Material<'mat>, second: &'mat Material<'mat>, weight: f32) -> Self {
+        Self::Mix {
+            first,
+            second,
+            weight: Texture::constant(Vec3::all(weight)),
+        }
+    }
+
+    /// Convenience method to construct a blend of two materials where
+    /// a texture masks between them.
+    pub fn mask(first: &'mat Material<'mat>, second: &'mat Material<'mat>, weight: Texture) -> Self {
+        Self::Mix {
+            first,
+            second,
+            weight,
+        }
+    }
+
+    /// Convenience method to construct a clear coat over a base material.
+    pub fn coated(base: &'mat Material<'mat>, ref_idx: f32) -> Self {
+        Self::Coated {
+            base,
+            coat: Coat::new(ref_idx),
+        }
+    }
+
     /// Process an incoming ray and return an option indicating if that ray
     /// has been scattered or completely absorbed.
-    pub fn scatter(&self, r_in: Ray, rec: &Hit) -> Scatter {
+    pub fn scatter(&self, r_in: Ray, rec: &Hit, sampler: &mut impl Sampler) -> Scatter {
         match self {
-            Material::Lambertian(mat) => mat.scatter(r_in, rec),
-            Material::Metal(mat) => mat.scatter(r_in, rec),
-            Material::Dielectric(mat) => mat.scatter(r_in, rec),
-            Material::Combined { scatterer, .. } => scatterer.scatter(r_in, rec),
+            Material::Lambertian(mat) => mat.scatter(r_in, rec, sampler),
+            Material::Metal(mat) => mat.scatter(r_in, rec, sampler),
+            Material::Dielectric(mat) => mat.scatter(r_in, rec, sampler),
+            Material::Subsurface(mat) => mat.scatter(r_in, rec, sampler),
+            Material::Combined { scatterer, .. } => scatterer.scatter(r_in, rec, sampler),
+            Material::Mix {
+                first,
+                second,
+                weight,
+            } => {
+                if sampler.next_1d() < Self::mix_weight(weight, rec) {
+                    second.scatter(r_in, rec, sampler)
+                } else {
+                    first.scatter(r_in, rec, sampler)
+                }
+            }
+            Material::Coated { base, coat } => match coat.scatter(r_in, rec, sampler) {
+                Some(scatter) => scatter,
+                None => base.scatter(r_in, rec, sampler),
+            },
+            Material::NormalMapped { base, map } => base.scatter(r_in, &map.perturb(rec), sampler),
+            Material::BumpMapped { base, map } => base.scatter(r_in, &map.perturb(rec), sampler),
             _ => Scatter::ZERO,
         }
     }
 
-    /// Get what the material emits.
+    /// Get what the material emits towards the origin of the incoming ray.
     ///
     /// # Assumptions
     /// This method assumes that the ray has already hit the object with
     /// this material.
-    pub fn emit(&self, rec: Hit) -> Vec3 {
+    pub fn emit(&self, r_in: Ray, rec: Hit) -> Vec3 {
         match self {
-            Material::DiffuseLight(mat) => mat.emit(rec),
-            Material::Combined { emitter, .. } => emitter.emit(rec),
+            Material::DiffuseLight(mat) => mat.emit(r_in, rec),
+            Material::Combined { emitter, .. } => emitter.emit(r_in, rec),
+            Material::Mix {
+                first,
+                second,
+                weight,
+            } => {
+                let t = Self::mix_weight(weight, &rec);
+                first.emit(r_in, rec).lerp(second.emit(r_in, rec), t)
+            }
+            Material::Coated { base, .. } => base.emit(r_in, rec),
+            Material::NormalMapped { base, map } => base.emit(r_in, map.perturb(&rec)),
+            Material::BumpMapped { base, map } => base.emit(r_in, map.perturb(&rec)),
             _ => Vec3::ZERO,
         }
     }
+
+    /// Get the base colour of the material at the hit point, for
+    /// feature buffers such as the albedo pass.
+    pub fn albedo(&self, rec: &Hit) -> Vec3 {
+        match self {
+            Material::Lambertian(mat) => mat.albedo,
+            Material::Metal(mat) => mat.albedo,
+            Material::Dielectric(_) => Vec3::ONE,
+            Material::Subsurface(mat) => mat.albedo,
+            Material::DiffuseLight(mat) => mat.emission.value(rec.uv, rec.point),
+            Material::Combined { scatterer, .. } => scatterer.albedo(rec),
+            Material::Mix {
+                first,
+                second,
+                weight,
+            } => first
+                .albedo(rec)
+                .lerp(second.albedo(rec), Self::mix_weight(weight, rec)),
+            Material::Coated { base, .. } => base.albedo(rec),
+            Material::NormalMapped { base, .. } | Material::BumpMapped { base, .. } => {
+                base.albedo(rec)
+            }
+        }
+    }
+
+    /// Name of the kind of material, for debugging output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Material::Lambertian(_) => "lambertian",
+            Material::Metal(_) => "metal",
+            Material::Dielectric(_) => "dielectric",
+            Material::Subsurface(_) => "subsurface",
+            Material::DiffuseLight(_) => "diffuse-light",
+            Material::Combined { .. } => "combined",
+            Material::Mix { .. } => "mix",
+            Material::Coated { .. } => "coated",
+            Material::NormalMapped { .. } => "normal-mapped",
+            Material::BumpMapped { .. } => "bump-mapped",
+        }
+    }
+
+    /// Whether the material scatters light into a sharp lobe, as mirrors
+    /// and glass do. Coated materials count as their base.
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Metal(_) | Material::Dielectric(_) => true,
+            Material::Combined { scatterer, .. } => scatterer.is_specular(),
+            Material::Mix { first, second, .. } => first.is_specular() && second.is_specular(),
+            Material::Coated { base, .. }
+            | Material::NormalMapped { base, .. }
+            | Material::BumpMapped { base, .. } => base.is_specular(),
+            _ => false,
+        }
+    }
+
+    /// Get the participating medium enclosed by surfaces of this material,
+    /// if there is one. Coated materials enclose the medium of their base.
+    pub fn medium(&self) -> Option<&Subsurface> {
+        match self {
+            Material::Subsurface(mat) => Some(mat),
+            Material::Combined { scatterer, .. } => scatterer.medium(),
+            Material::Coated { base, .. }
+            | Material::NormalMapped { base, .. }
+            | Material::BumpMapped { base, .. } => base.medium(),
+            _ => None,
+        }
+    }
+
+    // Grayscale value of the mask at the hit point.
+    fn mix_weight(weight: &Texture, rec: &Hit) -> f32 {
+        weight.scalar(rec.uv, rec.point).clamp(0.0, 1.0)
+    }
 }
 
 /// Lambertian reflective or matte material.
+///
+/// With a non-zero roughness this becomes the qualitative Oren-Nayar
+/// model, which flattens the look of rough surfaces such as clay or
+/// the moon.
 #[derive(Debug)]
 pub struct Lambertian {
     albedo: Vec3,
+    // Oren-Nayar coefficients, `a = 1` and `b = 0` for a perfect
+    // lambertian surface.
+    a: f32,
+    b: f32,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vec3) -> Self {
-        Self { albedo }
+        Self {
+            albedo,
+            a: 1.0,
+            b: 0.0,
+        }
+    }
+
+    pub fn oren_nayar(albedo: Vec3, roughness: f32) -> Self {
+        let sigma2 = roughness * roughness;
+        Self {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
     }
 
-    pub fn scatter(&self, _: Ray, rec: &Hit) -> Scatter {
-        let target = rec.point + rec.normal + rand::random::<Vec3>();
-        let scattered = Ray::new(rec.point, target - rec.point);
+    pub fn scatter(&self, r_in: Ray, rec: &Hit, sampler: &mut impl Sampler) -> Scatter {
+        // Importance sample the cosine term, which cancels out with the
+        // pdf so that a lambertian surface is attenuated by its albedo only.
+        let basis = Onb::from_w(rec.shading_normal);
+        let (u1, u2) = sampler.next_2d();
+        let local = onb::cosine_direction(u1, u2);
+        let scattered = rec.spawn_ray(basis.to_world(local));
         Scatter {
             scattered,
-            attenuation: self.albedo,
+            attenuation: self.albedo * self.oren_nayar_factor(basis, r_in, local),
         }
     }
+
+    // Ratio of the Oren-Nayar BRDF to the lambertian one for the given
+    // incoming ray and outgoing direction in the local frame.
+    fn oren_nayar_factor(&self, basis: Onb, r_in: Ray, wi: Vec3) -> f32 {
+        if self.b == 0.0 {
+            return self.a;
+        }
+
+        let wo = basis.to_local(-r_in.direction.normalize());
+        let cos_i = wi.z.abs();
+        let cos_o = wo.z.abs();
+        let sin_i = (1.0 - cos_i * cos_i).max(0.0).sqrt();
+        let sin_o = (1.0 - cos_o * cos_o).max(0.0).sqrt();
+
+        let max_cos = if sin_i > 1e-4 && sin_o > 1e-4 {
+            ((wi.x * wo.x + wi.y * wo.y) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i)
+        } else {
+            (sin_i, sin_o / cos_o.max(1e-4))
+        };
+
+        self.a + self.b * max_cos * sin_alpha * tan_beta
+    }
 }
 
 /// Reflective or metal material.
@@ -121,11 +385,15 @@ impl Metal {
         }
     }
 
-    pub fn scatter(&self, r_in: Ray, rec: &Hit) -> Scatter {
+    pub fn scatter(&self, r_in: Ray, rec: &Hit, sampler: &mut impl Sampler) -> Scatter {
         let target = r_in
             .direction /*.normalize()*/
-            .reflect(rec.normal);
-        let scattered = Ray::new(rec.point, target + rand::random::<Vec3>() * self.fuzz);
+            .reflect(rec.shading_normal);
+        let scattered = rec.spawn_ray(
+            target + sampler::unit_ball(sampler.next_2d(), sampler.next_1d()) * self.fuzz,
+        );
+        // Reflections must stay above the geometric surface, even when
+        // the shading normal leans away from it.
         if scattered.direction.dot(rec.normal) > 0.0 {
             Scatter {
                 scattered,
@@ -149,20 +417,20 @@ impl Dielectric {
         Self { ref_idx }
     }
 
-    pub fn scatter(&self, r_in: Ray, rec: &Hit) -> Scatter {
+    pub fn scatter(&self, r_in: Ray, rec: &Hit, sampler: &mut impl Sampler) -> Scatter {
         let outward_normal;
         let ni_over_nt;
         let cosine;
 
-        if r_in.direction.dot(rec.normal) > 0.0 {
-            outward_normal = -rec.normal;
+        if r_in.direction.dot(rec.shading_normal) > 0.0 {
+            outward_normal = -rec.shading_normal;
             ni_over_nt = self.ref_idx;
-            let _cosine = r_in.direction.dot(rec.normal) / r_in.direction.mag();
+            let _cosine = r_in.direction.dot(rec.shading_normal) / r_in.direction.mag();
             cosine = (1.0 - self.ref_idx * self.ref_idx * (1.0 - _cosine * _cosine)).sqrt();
         } else {
-            outward_normal = rec.normal;
+            outward_normal = rec.shading_normal;
             ni_over_nt = 1.0 / self.ref_idx;
-            cosine = -r_in.direction.dot(rec.normal) / r_in.direction.mag();
+            cosine = -r_in.direction.dot(rec.shading_normal) / r_in.direction.mag();
         }
 
         let refract_result = Self::refract(r_in.direction, outward_normal, ni_over_nt);
@@ -174,10 +442,10 @@ impl Dielectric {
         };
 
         Scatter {
-            scattered: if rand::random::<f32>() < reflect_probability {
-                Ray::new(rec.point, r_in.direction.reflect(rec.normal))
+            scattered: if sampler.next_1d() < reflect_probability {
+                rec.spawn_ray(r_in.direction.reflect(rec.shading_normal))
             } else {
-                Ray::new(rec.point, refract_result.unwrap_or_default())
+                rec.spawn_ray(refract_result.unwrap_or_default())
             },
             attenuation: Vec3::ONE,
         }
@@ -201,18 +469,341 @@ impl Dielectric {
     }
 }
 
+/// Translucent material where light refracts through a dielectric
+/// boundary and then performs a random walk through the volume inside.
+#[derive(Debug)]
+pub struct Subsurface {
+    interface: Dielectric,
+    // Probability of a ray surviving each scattering event, per channel.
+    albedo: Vec3,
+    // Average distance travelled between scattering events.
+    mean_free_path: f32,
+    // Henyey-Greenstein asymmetry parameter, positive values scatter
+    // forward and negative values scatter backward.
+    anisotropy: f32,
+}
+
+impl Subsurface {
+    pub fn new(ref_idx: f32, albedo: Vec3, mean_free_path: f32, anisotropy: f32) -> Self {
+        Self {
+            interface: Dielectric::new(ref_idx),
+            // Surviving more than every scattering event would add energy.
+            albedo: albedo.map(|c| c.clamp(0.0, 1.0)),
+            mean_free_path,
+            anisotropy: anisotropy.clamp(-0.999, 0.999),
+        }
+    }
+
+    /// Reflect or refract a ray at the boundary of the volume.
+    pub fn scatter(&self, r_in: Ray, rec: &Hit, sampler: &mut impl Sampler) -> Scatter {
+        self.interface.scatter(r_in, rec, sampler)
+    }
+
+    /// Sample the distance a ray travels inside the volume before its
+    /// next scattering event.
+    pub fn sample_distance(&self, sampler: &mut impl Sampler) -> f32 {
+        -(1.0 - sampler.next_1d()).ln() * self.mean_free_path
+    }
+
+    /// Scatter a ray at a point inside the volume by sampling the
+    /// Henyey-Greenstein phase function.
+    pub fn scatter_volume(&self, r_in: Ray, point: Vec3, sampler: &mut impl Sampler) -> Scatter {
+        let g = self.anisotropy;
+        let (xi, u) = sampler.next_2d();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * xi
+        } else {
+            let sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+            (1.0 + g * g - sq * sq) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * u;
+
+        let basis = Onb::from_w(r_in.direction.normalize());
+        let local = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+
+        Scatter {
+            scattered: Ray::new(point, basis.to_world(local)),
+            attenuation: self.albedo,
+        }
+    }
+}
+
 // Diffuse light-emitting material.
 #[derive(Debug)]
 pub struct DiffuseLight {
-    emittance: Vec3,
+    // Emitted radiance.
+    emission: Texture,
+    // Whether the back face, opposite of the normal, also emits.
+    two_sided: bool,
 }
 
 impl DiffuseLight {
     pub fn new(emittance: Vec3) -> Self {
-        Self { emittance }
+        Self::textured(Texture::constant(emittance), true)
+    }
+
+    pub fn textured(emission: Texture, two_sided: bool) -> Self {
+        Self {
+            emission,
+            two_sided,
+        }
     }
 
-    pub fn emit(&self, _: Hit) -> Vec3 {
-        self.emittance
+    /// Construct a one-sided light that emits a total power of `watts`
+    /// uniformly from a surface of the given `area`. The tint only sets
+    /// the colour, as it is scaled to a luminance of one. Black tints have
+    /// no colour to scale and emit nothing.
+    pub fn from_power(tint: Vec3, watts: f32, area: f32) -> Self {
+        // A lambertian emitter of radiance L emits pi * L per unit area.
+        let radiance = watts / (std::f32::consts::PI * area);
+        let luminance = color::luminance(tint);
+        let emission = if luminance > 0.0 {
+            tint / luminance * radiance
+        } else {
+            Vec3::ZERO
+        };
+        Self::textured(Texture::constant(emission), false)
+    }
+
+    /// Construct a one-sided light with the colour of a black body at
+    /// the given temperature in Kelvin.
+    pub fn blackbody(kelvin: f32, luminance: f32) -> Self {
+        Self::textured(
+            Texture::constant(color::blackbody(kelvin) * luminance),
+            false,
+        )
+    }
+
+    pub fn emit(&self, r_in: Ray, rec: Hit) -> Vec3 {
+        if !self.two_sided && r_in.direction.dot(rec.normal) > 0.0 {
+            return Vec3::ZERO;
+        }
+        self.emission.value(rec.uv, rec.point)
+    }
+}
+
+/// Clear dielectric layer on top of another material, such as the
+/// lacquer of car paint or the varnish on wood.
+#[derive(Debug)]
+pub struct Coat {
+    // Refraction index
+    ref_idx: f32,
+}
+
+impl Coat {
+    pub fn new(ref_idx: f32) -> Self {
+        Self { ref_idx }
+    }
+
+    /// Returns the specular reflection off the coat, or `None` if the ray
+    /// passes through the coat and should interact with the base instead.
+    pub fn scatter(&self, r_in: Ray, rec: &Hit, sampler: &mut impl Sampler) -> Option<Scatter> {
+        let cosine = -r_in.direction.dot(rec.shading_normal) / r_in.direction.mag();
+
+        // Rays leaving the object from inside never see the coat.
+        if cosine <= 0.0 {
+            return None;
+        }
+
+        if sampler.next_1d() < Dielectric::schlick(cosine, self.ref_idx) {
+            Some(Scatter {
+                scattered: rec.spawn_ray(r_in.direction.reflect(rec.shading_normal)),
+                attenuation: Vec3::ONE,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Independent;
+    use crate::vec3::vec3;
+
+    // A hit on the top of a unit sphere at the origin, seen from above at
+    // an angle.
+    fn hit<'mat>(material: &'mat Material<'mat>) -> (Ray, Hit<'mat>) {
+        let normal = vec3(0.0, 1.0, 0.0);
+        let hit = Hit {
+            parameter: 1.0,
+            point: normal,
+            normal,
+            shading_normal: normal,
+            tangent: vec3(1.0, 0.0, 0.0),
+            bitangent: vec3(0.0, 0.0, -1.0),
+            uv: (0.5, 0.5),
+            object: 1,
+            material,
+        };
+        (Ray::new(vec3(-1.0, 2.0, 0.0), vec3(1.0, -1.0, 0.0)), hit)
+    }
+
+    #[test]
+    fn smooth_oren_nayar_is_lambertian() {
+        let albedo = vec3(0.8, 0.5, 0.2);
+        let lambertian = Material::lambertian(albedo);
+        let oren_nayar = Material::oren_nayar(albedo, 0.0);
+        let (ray, rec) = hit(&lambertian);
+
+        let mut a = Independent::new(3);
+        let mut b = Independent::new(3);
+        for index in 0..1000 {
+            a.start_pixel_sample((0, 0), index);
+            b.start_pixel_sample((0, 0), index);
+            let expected = lambertian.scatter(ray, &rec, &mut a);
+            let scatter = oren_nayar.scatter(ray, &rec, &mut b);
+            assert_eq!(scatter.scattered, expected.scattered);
+            assert_eq!(scatter.attenuation, expected.attenuation);
+        }
+    }
+
+    #[test]
+    fn normal_mapped_mirror_reflects_about_mapped_normal() {
+        let mirror = Material::metal(Vec3::ONE, 0.0);
+        let tilt = Texture::constant(vec3(0.25, 0.5, 1.0));
+        let material = Material::normal_mapped(&mirror, tilt, 1.0);
+        let (ray, rec) = hit(&material);
+
+        let mut sampler = Independent::new(1);
+        sampler.start_pixel_sample((0, 0), 0);
+        let scatter = material.scatter(ray, &rec, &mut sampler);
+        let normal = vec3(-0.5, 1.0, 0.0).normalize();
+        let expected = ray.direction.reflect(normal);
+        assert!((scatter.scattered.direction - expected).mag() < 1e-4);
+        assert_eq!(scatter.attenuation, Vec3::ONE);
+    }
+
+    #[test]
+    fn flat_bump_mapped_material_is_its_base() {
+        let base = Material::lambertian(vec3(0.5, 0.25, 1.0));
+        let material = Material::bump_mapped(&base, Texture::constant(Vec3::all(0.5)), 1.0);
+        let (ray, rec) = hit(&material);
+
+        let mut a = Independent::new(2);
+        let mut b = Independent::new(2);
+        a.start_pixel_sample((0, 0), 0);
+        b.start_pixel_sample((0, 0), 0);
+        let expected = base.scatter(ray, &rec, &mut a);
+        let scatter = material.scatter(ray, &rec, &mut b);
+        assert!((scatter.scattered.direction - expected.scattered.direction).mag() < 1e-5);
+        assert_eq!(scatter.attenuation, expected.attenuation);
+        assert_eq!(material.albedo(&rec), base.albedo(&rec));
+    }
+
+    #[test]
+    fn one_sided_lights_emit_from_the_front_only() {
+        let lights = [
+            Material::light_from_power(vec3(1.0, 0.5, 0.25), 10.0, 2.0),
+            Material::blackbody_light(3000.0, 2.0),
+            Material::textured_light(Texture::constant(Vec3::ONE), false),
+        ];
+        for light in &lights {
+            let (ray, rec) = hit(light);
+            assert_ne!(light.emit(ray, rec), Vec3::ZERO);
+            let behind = Ray::new(vec3(-1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0));
+            assert_eq!(light.emit(behind, rec), Vec3::ZERO);
+        }
+    }
+
+    #[test]
+    fn light_from_power_emits_its_power() {
+        let light = Material::light_from_power(vec3(1.0, 0.5, 0.25), 10.0, 2.0);
+        let (ray, rec) = hit(&light);
+        let radiance = color::luminance(light.emit(ray, rec));
+        let expected = 10.0 / (std::f32::consts::PI * 2.0);
+        assert!((radiance - expected).abs() < 1e-4, "{}", radiance);
+
+        let black = Material::light_from_power(Vec3::ZERO, 10.0, 2.0);
+        assert_eq!(black.emit(ray, rec), Vec3::ZERO);
+    }
+
+    #[test]
+    fn blackbody_light_has_its_luminance() {
+        for &kelvin in &[2700.0, 6500.0] {
+            let light = Material::blackbody_light(kelvin, 3.0);
+            let (ray, rec) = hit(&light);
+            let luminance = color::luminance(light.emit(ray, rec));
+            assert!((luminance - 3.0).abs() < 0.05, "{}", luminance);
+        }
+        // Warm lights are redder than cool ones.
+        let (warm, cool) = (color::blackbody(2700.0), color::blackbody(9000.0));
+        assert!(warm.x / warm.z > cool.x / cool.z);
+    }
+
+    #[test]
+    fn textured_light_follows_its_texture() {
+        let red = vec3(1.0, 0.0, 0.0);
+        let blue = vec3(0.0, 0.0, 1.0);
+        let image = crate::texture::ImageTexture::new(2, 1, vec![red, blue]);
+        let light = Material::textured_light(Texture::Image(image), true);
+        let (ray, mut rec) = hit(&light);
+        rec.uv = (0.25, 0.5);
+        assert_eq!(light.emit(ray, rec), red);
+        rec.uv = (0.75, 0.5);
+        assert_eq!(light.emit(ray, rec), blue);
+
+        // Two-sided lights also emit from behind.
+        let behind = Ray::new(vec3(-1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0));
+        assert_eq!(light.emit(behind, rec), blue);
+    }
+
+    // Throughput never grows along a random walk through the volume, even
+    // with too high an albedo, and the phase function scatters forward as
+    // much as its anisotropy says.
+    #[test]
+    fn subsurface_walk_conserves_energy() {
+        let material = Material::subsurface(1.3, vec3(0.9, 0.7, 1.5), 0.1, 0.6);
+        let medium = material.medium().unwrap();
+        let (ray, rec) = hit(&material);
+
+        let mut sampler = Independent::new(5);
+        let mut cosines = 0.0;
+        let mut events = 0;
+        for index in 0..1000 {
+            sampler.start_pixel_sample((0, 0), index);
+            let entered = material.scatter(ray, &rec, &mut sampler);
+            assert!(entered.attenuation.max_component() <= 1.0);
+
+            let mut ray = entered.scattered;
+            let mut throughput = entered.attenuation;
+            for _ in 0..50 {
+                assert!(medium.sample_distance(&mut sampler) >= 0.0);
+                let scatter = medium.scatter_volume(ray, ray.origin, &mut sampler);
+                let next = throughput * scatter.attenuation;
+                assert!(next.x <= throughput.x && next.y <= throughput.y && next.z <= throughput.z);
+                assert!((scatter.scattered.direction.mag() - 1.0).abs() < 1e-4);
+
+                cosines += scatter.scattered.direction.dot(ray.direction.normalize());
+                events += 1;
+                ray = scatter.scattered;
+                throughput = next;
+            }
+        }
+
+        let mean = cosines / events as f32;
+        assert!((mean - 0.6).abs() < 0.02, "mean cosine {}", mean);
+    }
+
+    // Light refracted through the coat of a subsurface material walks
+    // through its volume, as it would without the coat.
+    #[test]
+    fn coated_subsurface_encloses_its_medium() {
+        let base = Material::subsurface(1.3, vec3(0.9, 0.7, 0.5), 0.1, 0.0);
+        let coated = Material::coated(&base, 1.5);
+        let medium = coated.medium().expect("coated subsurface has a medium");
+        assert!(std::ptr::eq(medium, base.medium().unwrap()));
+
+        // Some rays get through the coat into the object.
+        let (ray, rec) = hit(&coated);
+        let mut sampler = Independent::new(3);
+        let entered = (0..100).any(|index| {
+            sampler.start_pixel_sample((0, 0), index);
+            let scatter = coated.scatter(ray, &rec, &mut sampler);
+            scatter.scattered.direction.dot(rec.normal) < 0.0
+        });
+        assert!(entered);
     }
 }
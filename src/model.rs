@@ -1,17 +1,58 @@
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vec3::Vec3;
+use crate::vec3::{vec3, Vec3};
 
 // If this is returned, then it means that the ray of light hit the object
 // for some parameter at a point. The normal and material of the object
 // is also returned.
+#[derive(Clone, Copy)]
 pub struct Hit<'mat> {
     pub parameter: f32,
     pub point: Vec3,
+    // Geometric normal of the surface, used to keep rays on the right side.
     pub normal: Vec3,
+    // Normal used for shading, which normal or bump maps may perturb.
+    pub shading_normal: Vec3,
+    // Unit directions along increasing u and v on the surface.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
+    // Texture coordinates of the point on the surface.
+    pub uv: (f32, f32),
+    // One-based index of the object in the top level list of the scene.
+    pub object: u32,
     pub material: &'mat Material<'mat>,
 }
 
+impl Hit<'_> {
+    // Offset used to move ray origins off the surface.
+    const EPSILON: f32 = 1e-4;
+
+    // Construct a ray leaving the hit point, nudged along the geometric
+    // normal towards the side the direction points to so it does not
+    // immediately hit the same surface again.
+    pub fn spawn_ray(&self, direction: Vec3) -> Ray {
+        let offset = self.normal * Self::EPSILON;
+        let origin = if direction.dot(self.normal) > 0.0 {
+            self.point + offset
+        } else {
+            self.point - offset
+        };
+        Ray::new(origin, direction)
+    }
+}
+
+// Number of ray intersection tests against each type of primitive.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct IntersectionTests {
+    pub spheres: u64,
+}
+
+impl IntersectionTests {
+    pub fn total(&self) -> u64 {
+        self.spheres
+    }
+}
+
 // 3D model enumeration to avoid dynamic dispatch.
 #[non_exhaustive]
 pub enum Model<'mat> {
@@ -30,18 +71,42 @@ impl<'mat> Model<'mat> {
         Model::List(vec)
     }
 
+    // All materials used by the object(s), possibly with repetitions.
+    pub fn materials(&self) -> Vec<&Material<'mat>> {
+        match self {
+            Model::Sphere(s) => vec![s.material],
+            Model::List(list) => list.iter().flat_map(Model::materials).collect(),
+        }
+    }
+
     // Test if the ray of light hits the object(s) within a certain parameter range.
-    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
+        self.hit_counting(ray, t_min, t_max, &mut IntersectionTests::default())
+    }
+
+    // Same as `hit`, adding the primitives tested to the counts.
+    pub fn hit_counting(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+        tests: &mut IntersectionTests,
+    ) -> Option<Hit<'_>> {
         match self {
-            Model::Sphere(s) => s.hit(ray, t_min, t_max),
+            Model::Sphere(s) => {
+                tests.spheres += 1;
+                s.hit(ray, t_min, t_max)
+            }
             Model::List(list) => {
                 let mut hit_record = None;
 
                 let mut closest_so_far = t_max;
 
-                for hittable in list {
-                    if let Some(hit) = hittable.hit(ray, t_min, closest_so_far) {
+                for (index, hittable) in list.iter().enumerate() {
+                    if let Some(mut hit) = hittable.hit_counting(ray, t_min, closest_so_far, tests)
+                    {
                         closest_so_far = hit.parameter;
+                        hit.object = index as u32 + 1;
                         hit_record = Some(hit);
                     }
                 }
@@ -68,7 +133,7 @@ impl<'mat> Sphere<'mat> {
         }
     }
 
-    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit> {
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Hit<'_>> {
         // Quadratic formula this boy.
         let oc = ray.origin - self.center;
         let a = ray.direction.dot(ray.direction);
@@ -81,30 +146,53 @@ impl<'mat> Sphere<'mat> {
             let parameter = (-b - discriminant.sqrt()) / a;
 
             if t_min < parameter && parameter < t_max {
-                let point = ray.point_at_parameter(parameter);
-
-                return Some(Hit {
-                    parameter,
-                    point,
-                    normal: (point - self.center) / self.radius,
-                    material: &self.material
-                })
+                return Some(self.hit_at(ray, parameter));
             }
 
             let parameter = (-b + discriminant.sqrt()) / a;
 
             if t_min < parameter && parameter < t_max {
-                let point = ray.point_at_parameter(parameter);
-
-                return Some(Hit {
-                    parameter,
-                    point,
-                    normal: (point - self.center) / self.radius,
-                    material: &self.material
-                })
+                return Some(self.hit_at(ray, parameter));
             }
         }
 
         None
     }
-}
\ No newline at end of file
+
+    // Fill in the surface details at the given parameter along the ray.
+    fn hit_at(&self, ray: &Ray, parameter: f32) -> Hit<'_> {
+        let point = ray.point_at_parameter(parameter);
+        let normal = (point - self.center) / self.radius;
+
+        // Spherical coordinates of the point, with the poles on the y axis.
+        let p = normal * self.radius.signum();
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + std::f32::consts::PI;
+        let uv = (
+            phi / (2.0 * std::f32::consts::PI),
+            theta / std::f32::consts::PI,
+        );
+
+        // The tangent follows the lines of latitude, and degenerates
+        // at the poles where any perpendicular direction will do.
+        let tangent = vec3(p.z, 0.0, -p.x);
+        let tangent = if tangent.mag_sq() > 1e-12 {
+            tangent.normalize()
+        } else {
+            vec3(1.0, 0.0, 0.0)
+        };
+        let bitangent = p.cross(tangent);
+
+        Hit {
+            parameter,
+            point,
+            normal,
+            shading_normal: normal,
+            tangent,
+            bitangent,
+            uv,
+            object: 0,
+            material: self.material,
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::vec3::{vec3, Vec3};
+
+/// Orthonormal basis used to move directions between world space and
+/// a local frame where `w` is the up axis, usually a surface normal.
+#[derive(Debug, Copy, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    /// Build a basis around the unit vector `w`.
+    ///
+    /// Uses the branchless construction from Duff et al. (2017),
+    /// "Building an Orthonormal Basis, Revisited".
+    pub fn from_w(w: Vec3) -> Self {
+        let sign = 1f32.copysign(w.z);
+        let a = -1.0 / (sign + w.z);
+        let b = w.x * w.y * a;
+        let u = vec3(1.0 + sign * w.x * w.x * a, sign * b, -sign * w.x);
+        let v = vec3(b, sign + w.y * w.y * a, -w.y);
+        Self { u, v, w }
+    }
+
+    /// Transform a direction expressed in this basis to world space.
+    pub fn to_world(&self, a: Vec3) -> Vec3 {
+        self.u * a.x + self.v * a.y + self.w * a.z
+    }
+
+    /// Transform a world space direction into this basis.
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        vec3(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
+
+/// Map two uniform numbers in `[0, 1)` to a direction on the hemisphere
+/// around `+z`, distributed proportionally to the cosine of the angle
+/// with `+z` (Malley's method).
+pub fn cosine_direction(u1: f32, u2: f32) -> Vec3 {
+    let phi = 2.0 * std::f32::consts::PI * u1;
+    let r = u2.sqrt();
+    vec3(r * phi.cos(), r * phi.sin(), (1.0 - u2).max(0.0).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Rng;
+
+    use std::f32::consts::PI;
+
+    // Pearson's chi-square test of directions binned by `cos(theta)` and
+    // `phi` against the `cos(theta) / pi` density.
+    #[test]
+    fn cosine_direction_follows_cosine_density() {
+        const THETA_BINS: usize = 10;
+        const PHI_BINS: usize = 20;
+        const SAMPLES: usize = 200_000;
+
+        let mut counts = [[0u32; PHI_BINS]; THETA_BINS];
+        let mut rng = Rng::new(1, 2);
+        for _ in 0..SAMPLES {
+            let d = cosine_direction(rng.next_f32(), rng.next_f32());
+            assert!((d.mag() - 1.0).abs() < 1e-4 && d.z >= 0.0);
+            let phi = d.y.atan2(d.x).rem_euclid(2.0 * PI);
+            let theta_bin = ((d.z * THETA_BINS as f32) as usize).min(THETA_BINS - 1);
+            let phi_bin = ((phi / (2.0 * PI) * PHI_BINS as f32) as usize).min(PHI_BINS - 1);
+            counts[theta_bin][phi_bin] += 1;
+        }
+
+        // Integrating `cos(theta) / pi` over a bin gives the difference of
+        // the squared cosines at its edges over two, times its share of
+        // the circle.
+        let mut chi2 = 0.0;
+        for (i, row) in counts.iter().enumerate() {
+            let (c0, c1) = (
+                i as f64 / THETA_BINS as f64,
+                (i + 1) as f64 / THETA_BINS as f64,
+            );
+            let expected = (c1 * c1 - c0 * c0) / PHI_BINS as f64 * SAMPLES as f64;
+            for &count in row {
+                chi2 += (count as f64 - expected).powi(2) / expected;
+            }
+        }
+
+        // Wilson-Hilferty approximation of the 99.9th percentile of the
+        // chi-square distribution.
+        let dof = (THETA_BINS * PHI_BINS - 1) as f64;
+        let h = 2.0 / (9.0 * dof);
+        let critical = dof * (1.0 - h + 3.09 * h.sqrt()).powi(3);
+        assert!(chi2 < critical, "chi-square {} above {}", chi2, critical);
+    }
+}
@@ -0,0 +1,345 @@
+use raytracer::denoise::Denoiser;
+use raytracer::filter::Filter;
+use raytracer::integrator::Integrator;
+use raytracer::output::PixelType;
+use raytracer::render::Cost;
+use raytracer::sampler::SamplerKind;
+use raytracer::tile::TileOrder;
+use raytracer::tonemap::{DisplayTransform, ToneMapper};
+
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Settings of a render, read from the command line.
+pub struct Options {
+    // Text file describing the scene, the built-in one if unset.
+    pub scene: Option<PathBuf>,
+    // Image parameters.
+    pub width: u32,
+    pub height: u32,
+    // With adaptive sampling, `samples` is the most a pixel may take.
+    pub samples: u32,
+    pub min_samples: u32,
+    // Relative error under which pixels stop being sampled, if adaptive.
+    pub adaptive: Option<f32>,
+    // Write an image of the number of samples taken in every pixel.
+    pub sample_heatmap: bool,
+    // Write an image of the work spent on every pixel, by this measure.
+    pub cost_heatmap: Option<Cost>,
+    // Print counts of the rays traced and the time taken once done, and
+    // write them as JSON to this file if set.
+    pub stats: bool,
+    pub stats_json: Option<PathBuf>,
+    // Render in passes of doubling sample counts, saving the image to the
+    // output after each pass, or at most this often in seconds if set.
+    pub progressive: bool,
+    pub snapshot_interval: Option<f32>,
+    // Stop sampling after this many seconds, whatever the sample count.
+    pub time_limit: Option<f32>,
+    // Where the state of the render is saved at most every interval in
+    // seconds, and whether to continue from the state saved there.
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: f32,
+    pub resume: bool,
+    // Name of the sampler generating the random numbers of each path.
+    pub sampler: String,
+    // Renders with the same seed and settings are identical.
+    pub seed: u32,
+    // Bounces after which paths may be ended by Russian roulette, or only
+    // at the bounce limit if unset, as by default.
+    pub roulette_depth: Option<i32>,
+    // Firefly suppression, all off for unbiased renders: the most light a
+    // sample may bring directly and indirectly, and the roughness added to
+    // specular scattering with every non-specular bounce of a path.
+    pub clamp_direct: Option<f32>,
+    pub clamp_indirect: Option<f32>,
+    pub regularization: Option<f32>,
+    // Integrator estimating the light of every sample, with the distance
+    // ambient occlusion looks for occluders within if set.
+    pub integrator: Integrator,
+    pub ao_distance: Option<f32>,
+    // Reconstruction filter, with its default radius if unset.
+    pub filter: Filter,
+    pub filter_radius: Option<f32>,
+    // Size of the square tiles the image is rendered in, and the order
+    // they are rendered in.
+    pub tile_size: u32,
+    pub tile_order: TileOrder,
+    // Where the beauty render is written, in a format picked from the
+    // extension, along with a linear OpenEXR copy next to it if asked.
+    // OpenEXR images store this type of float.
+    pub output: PathBuf,
+    pub linear_output: bool,
+    pub exr_precision: PixelType,
+    // Display transform parameters.
+    pub exposure: f32,
+    pub white_balance: Option<f32>,
+    pub tone_mapper: ToneMapper,
+    // Format of the render passes, either `exr` for a single multi-layer
+    // image or the extension of one image per pass. No passes if unset.
+    pub aovs: Option<String>,
+    // Instead of rendering, log the paths of a range of samples of a pixel,
+    // all of them if unset, and write them as lines to an OBJ file if set.
+    pub trace_pixel: Option<(u32, u32)>,
+    pub trace_samples: Option<Range<u32>>,
+    pub trace_obj: Option<PathBuf>,
+    // Denoise the film before it is saved, with this many filter passes.
+    pub denoise: Option<u32>,
+    // Address to hand out tiles to workers on, instead of rendering them
+    // here, or of the coordinator to render tiles for.
+    pub coordinator: Option<String>,
+    pub worker: Option<String>,
+    // Address to serve render jobs over HTTP on, writing their images
+    // next to the output.
+    pub serve: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            width: 900,
+            height: 600,
+            samples: 1000,
+            min_samples: 16,
+            adaptive: None,
+            sample_heatmap: false,
+            cost_heatmap: None,
+            stats: false,
+            stats_json: None,
+            progressive: false,
+            snapshot_interval: None,
+            time_limit: None,
+            checkpoint: None,
+            checkpoint_interval: 60.0,
+            resume: false,
+            sampler: "independent".to_owned(),
+            seed: 0,
+            roulette_depth: None,
+            clamp_direct: None,
+            clamp_indirect: None,
+            regularization: None,
+            integrator: Integrator::default(),
+            ao_distance: None,
+            filter: Filter::default(),
+            filter_radius: None,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            output: PathBuf::from("./output/default.png"),
+            linear_output: false,
+            exr_precision: PixelType::Half,
+            exposure: 0.0,
+            white_balance: None,
+            tone_mapper: ToneMapper::default(),
+            aovs: None,
+            trace_pixel: None,
+            trace_samples: None,
+            trace_obj: None,
+            denoise: None,
+            coordinator: None,
+            worker: None,
+            serve: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parse `--flag value` pairs and `--flag` switches, starting from
+    /// the defaults.
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> io::Result<Self> {
+        let mut options = Self::default();
+
+        while let Some(flag) = args.next() {
+            match flag.as_str() {
+                "--scene" => options.scene = Some(value(&flag, &mut args)?),
+                "--width" => options.width = value(&flag, &mut args)?,
+                "--height" => options.height = value(&flag, &mut args)?,
+                "--samples" => options.samples = value(&flag, &mut args)?,
+                "--min-samples" => options.min_samples = value(&flag, &mut args)?,
+                "--adaptive" => options.adaptive = Some(value(&flag, &mut args)?),
+                "--sample-heatmap" => options.sample_heatmap = true,
+                "--cost-heatmap" => options.cost_heatmap = Some(value(&flag, &mut args)?),
+                "--stats" => options.stats = true,
+                "--stats-json" => options.stats_json = Some(value(&flag, &mut args)?),
+                "--progressive" => options.progressive = true,
+                "--snapshot-interval" => options.snapshot_interval = Some(value(&flag, &mut args)?),
+                "--time-limit" => options.time_limit = Some(value(&flag, &mut args)?),
+                "--checkpoint" => options.checkpoint = Some(value(&flag, &mut args)?),
+                "--checkpoint-interval" => options.checkpoint_interval = value(&flag, &mut args)?,
+                "--resume" => options.resume = true,
+                "--sampler" => {
+                    options.sampler = value(&flag, &mut args)?;
+                    if !SamplerKind::NAMES.contains(&options.sampler.as_str()) {
+                        return Err(invalid(format!(
+                            "unknown sampler `{}`, expected one of {}",
+                            options.sampler,
+                            SamplerKind::NAMES.join(", ")
+                        )));
+                    }
+                }
+                "--seed" => options.seed = value(&flag, &mut args)?,
+                "--roulette-depth" => options.roulette_depth = Some(value(&flag, &mut args)?),
+                "--no-roulette" => options.roulette_depth = None,
+                "--clamp-direct" => options.clamp_direct = Some(value(&flag, &mut args)?),
+                "--clamp-indirect" => options.clamp_indirect = Some(value(&flag, &mut args)?),
+                "--regularize" => options.regularization = Some(value(&flag, &mut args)?),
+                "--integrator" => options.integrator = value(&flag, &mut args)?,
+                "--ao-distance" => options.ao_distance = Some(value(&flag, &mut args)?),
+                "--filter" => options.filter = value(&flag, &mut args)?,
+                "--filter-radius" => options.filter_radius = Some(value(&flag, &mut args)?),
+                "--tile-size" => options.tile_size = value(&flag, &mut args)?,
+                "--tile-order" => options.tile_order = value(&flag, &mut args)?,
+                "--output" => options.output = value(&flag, &mut args)?,
+                "--linear-output" => options.linear_output = true,
+                "--exr-precision" => options.exr_precision = value(&flag, &mut args)?,
+                "--exposure" => options.exposure = value(&flag, &mut args)?,
+                "--white-balance" => options.white_balance = Some(value(&flag, &mut args)?),
+                "--tone-mapper" => options.tone_mapper = value(&flag, &mut args)?,
+                "--aovs" => options.aovs = Some(value(&flag, &mut args)?),
+                "--denoise" => options.denoise = Some(Denoiser::default().iterations),
+                "--denoise-iterations" => options.denoise = Some(value(&flag, &mut args)?),
+                "--trace-pixel" => {
+                    let pixel: String = value(&flag, &mut args)?;
+                    options.trace_pixel = Some(parse_pixel(&flag, &pixel)?);
+                }
+                "--trace-samples" => {
+                    let samples: String = value(&flag, &mut args)?;
+                    options.trace_samples = Some(parse_range(&flag, &samples)?);
+                }
+                "--trace-obj" => options.trace_obj = Some(value(&flag, &mut args)?),
+                "--coordinator" => options.coordinator = Some(value(&flag, &mut args)?),
+                "--worker" => options.worker = Some(value(&flag, &mut args)?),
+                "--serve" => options.serve = Some(value(&flag, &mut args)?),
+                _ => return Err(invalid(format!("unknown option `{}`", flag))),
+            }
+        }
+
+        if options.width == 0 || options.height == 0 {
+            return Err(invalid("images need at least one pixel".to_owned()));
+        }
+        if options.resume && options.checkpoint.is_none() {
+            return Err(invalid("`--resume` needs a `--checkpoint` file".to_owned()));
+        }
+        if options.coordinator.is_some()
+            && (options.progressive || options.time_limit.is_some() || options.resume)
+        {
+            return Err(invalid(
+                "`--coordinator` renders in a single pass, without `--progressive`, \
+                 `--time-limit` or `--resume`"
+                    .to_owned(),
+            ));
+        }
+        if options.coordinator.is_some() && options.worker.is_some() {
+            return Err(invalid(
+                "`--coordinator` and `--worker` are exclusive".to_owned(),
+            ));
+        }
+
+        Ok(options)
+    }
+
+    /// Arguments of a job for workers to render, without the ones that
+    /// only concern the coordinator.
+    pub fn job(args: &[String]) -> Vec<String> {
+        let mut job = Vec::with_capacity(args.len());
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--coordinator" {
+                args.next();
+            } else {
+                job.push(arg.clone());
+            }
+        }
+        job
+    }
+
+    /// The sampler prototype, cloned for every sample of the render.
+    pub fn sampler(&self) -> SamplerKind {
+        SamplerKind::from_name(&self.sampler, self.samples, self.seed)
+            .expect("sampler name is validated")
+    }
+
+    /// The integrator, with the ambient occlusion distance set.
+    pub fn integrator(&self) -> Integrator {
+        match (self.integrator, self.ao_distance) {
+            (Integrator::AmbientOcclusion { .. }, Some(distance)) => {
+                Integrator::AmbientOcclusion { distance }
+            }
+            (integrator, _) => integrator,
+        }
+    }
+
+    /// Radius of the reconstruction filter, in pixels.
+    pub fn filter_radius(&self) -> f32 {
+        self.filter_radius
+            .unwrap_or_else(|| self.filter.default_radius())
+    }
+
+    /// Where the linear OpenEXR copy of the render goes, if one is asked
+    /// for and the render is not written as OpenEXR already.
+    pub fn linear_output(&self) -> Option<PathBuf> {
+        let extension = self.output.extension().and_then(|ext| ext.to_str());
+        if !self.linear_output || extension.is_some_and(|ext| ext.eq_ignore_ascii_case("exr")) {
+            return None;
+        }
+        Some(self.output.with_extension("exr"))
+    }
+
+    /// The transform turning the linear film into displayable colours.
+    pub fn display(&self) -> DisplayTransform {
+        let display = DisplayTransform::new(self.exposure, self.tone_mapper);
+        match self.white_balance {
+            Some(kelvin) => display.with_white_balance(kelvin),
+            None => display,
+        }
+    }
+}
+
+// Parse the value following a flag.
+fn value<T>(flag: &str, args: &mut impl Iterator<Item = String>) -> io::Result<T>
+where
+    T: FromStr,
+    T::Err: ToString,
+{
+    let value = args
+        .next()
+        .ok_or_else(|| invalid(format!("missing value for `{}`", flag)))?;
+    value
+        .parse()
+        .map_err(|e: T::Err| invalid(format!("invalid value for `{}`: {}", flag, e.to_string())))
+}
+
+// Parse a pixel given as `x,y`.
+fn parse_pixel(flag: &str, s: &str) -> io::Result<(u32, u32)> {
+    let error = || invalid(format!("invalid value for `{}`: expected `x,y`", flag));
+    let (x, y) = s.split_once(',').ok_or_else(error)?;
+    Ok((
+        x.trim().parse().map_err(|_| error())?,
+        y.trim().parse().map_err(|_| error())?,
+    ))
+}
+
+// Parse a range of samples given as `start..end`, or a single sample.
+fn parse_range(flag: &str, s: &str) -> io::Result<Range<u32>> {
+    let error = || {
+        invalid(format!(
+            "invalid value for `{}`: expected `start..end`",
+            flag
+        ))
+    };
+    match s.split_once("..") {
+        Some((start, end)) => {
+            Ok(start.parse().map_err(|_| error())?..end.parse().map_err(|_| error())?)
+        }
+        None => {
+            let sample: u32 = s.parse().map_err(|_| error())?;
+            Ok(sample..sample.checked_add(1).ok_or_else(error)?)
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
@@ -0,0 +1,289 @@
+use half::f16;
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+/// Storage type of the channels of an OpenEXR image.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PixelType {
+    /// 16-bit half precision floats.
+    Half,
+    /// 32-bit single precision floats.
+    Float,
+}
+
+impl FromStr for PixelType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "half" => Ok(PixelType::Half),
+            "float" => Ok(PixelType::Float),
+            _ => Err(format!("unknown pixel type `{}`", s)),
+        }
+    }
+}
+
+impl PixelType {
+    // Identifier of the pixel type in the EXR channel list.
+    fn id(self) -> i32 {
+        match self {
+            PixelType::Half => 1,
+            PixelType::Float => 2,
+        }
+    }
+
+    // Size of a single value in bytes.
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Float => 4,
+        }
+    }
+}
+
+/// A named channel of values in row-major order, starting at the top row.
+///
+/// Channels of the same layer share a prefix, such as `albedo.R`.
+pub struct Channel<'a> {
+    pub name: String,
+    pub data: &'a [f32],
+}
+
+impl<'a> Channel<'a> {
+    pub fn new(name: impl Into<String>, data: &'a [f32]) -> Self {
+        Self {
+            name: name.into(),
+            data,
+        }
+    }
+}
+
+/// Write an uncompressed scanline OpenEXR image with any number of channels.
+pub fn write_exr(
+    mut w: impl Write,
+    width: u32,
+    height: u32,
+    channels: &[Channel],
+    pixel_type: PixelType,
+) -> io::Result<()> {
+    // Channels must be stored in alphabetical order.
+    let mut channels = channels.iter().collect::<Vec<_>>();
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+    for channel in &channels {
+        if channel.data.len() != width as usize * height as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("channel {} does not match the image size", channel.name),
+            ));
+        }
+    }
+
+    let mut header = Vec::new();
+
+    // Magic number, then version 2 with no flags for a single part scanline image.
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    let mut chlist = Vec::new();
+    for channel in &channels {
+        chlist.extend_from_slice(channel.name.as_bytes());
+        chlist.push(0);
+        chlist.extend_from_slice(&pixel_type.id().to_le_bytes());
+        // pLinear and three reserved bytes.
+        chlist.extend_from_slice(&[0; 4]);
+        // x and y sampling.
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+        chlist.extend_from_slice(&1i32.to_le_bytes());
+    }
+    chlist.push(0);
+    attribute(&mut header, "channels", "chlist", &chlist);
+
+    // No compression.
+    attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for v in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+
+    // Increasing y.
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    // Offset table pointing at each scanline, which are stored back to back
+    // right after the table.
+    let line_size = 8 + channels.len() * width as usize * pixel_type.size();
+    let first_line = header.len() + height as usize * 8;
+    for y in 0..height as usize {
+        header.extend_from_slice(&((first_line + y * line_size) as u64).to_le_bytes());
+    }
+    w.write_all(&header)?;
+
+    let mut line = Vec::with_capacity(line_size);
+    for y in 0..height as usize {
+        line.clear();
+        line.extend_from_slice(&(y as i32).to_le_bytes());
+        line.extend_from_slice(&((line_size - 8) as i32).to_le_bytes());
+        for channel in &channels {
+            let row = &channel.data[y * width as usize..(y + 1) * width as usize];
+            for &value in row {
+                match pixel_type {
+                    PixelType::Half => {
+                        line.extend_from_slice(&f16::from_f32(value).to_bits().to_le_bytes())
+                    }
+                    PixelType::Float => line.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        w.write_all(&line)?;
+    }
+
+    Ok(())
+}
+
+// Append an EXR header attribute.
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Write a colour Portable Float Map, with `data` holding interleaved
+/// RGB values in row-major order starting at the top row.
+pub fn write_pfm(mut w: impl Write, width: u32, height: u32, data: &[f32]) -> io::Result<()> {
+    // A negative scale marks little-endian data.
+    write!(w, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    // Scanlines are stored from the bottom row up.
+    let stride = 3 * width as usize;
+    let mut bytes = Vec::with_capacity(data.len() * 4);
+    for row in data.chunks(stride).rev() {
+        for value in row {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    w.write_all(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryInto;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    // Read a null terminated string, returning it and the position after it.
+    fn str_at(bytes: &[u8], at: usize) -> (&str, usize) {
+        let end = at + bytes[at..].iter().position(|&b| b == 0).unwrap();
+        (std::str::from_utf8(&bytes[at..end]).unwrap(), end + 1)
+    }
+
+    #[test]
+    fn exr_chunks_follow_the_offset_table() {
+        let (red, green, blue) = ([1.0, 2.0, 3.0, 4.0], [0.5; 4], [-1.0, 0.0, 0.25, 8.0]);
+        // Given out of order, as they must be sorted in the file.
+        let channels = [
+            Channel::new("R", &red),
+            Channel::new("G", &green),
+            Channel::new("B", &blue),
+        ];
+
+        for pixel_type in [PixelType::Half, PixelType::Float] {
+            let mut exr = Vec::new();
+            write_exr(&mut exr, 2, 2, &channels, pixel_type).unwrap();
+            assert_eq!(exr[..4], [0x76, 0x2f, 0x31, 0x01]);
+            assert_eq!(u32_at(&exr, 4), 2);
+
+            // Attributes, up to the empty name ending the header.
+            let mut at = 8;
+            let mut names = Vec::new();
+            loop {
+                let (name, next) = str_at(&exr, at);
+                if name.is_empty() {
+                    at = next;
+                    break;
+                }
+                let (kind, next) = str_at(&exr, next);
+                let size = u32_at(&exr, next) as usize;
+                let value = &exr[next + 4..next + 4 + size];
+                if name == "channels" {
+                    assert_eq!(kind, "chlist");
+                    let mut at = 0;
+                    while value[at] != 0 {
+                        let (channel, next) = str_at(value, at);
+                        names.push(channel.to_owned());
+                        assert_eq!(u32_at(value, next), pixel_type.id() as u32);
+                        at = next + 16;
+                    }
+                }
+                at = next + 4 + size;
+            }
+            assert_eq!(names, ["B", "G", "R"]);
+
+            let size = pixel_type.size();
+            let line_size = 8 + 3 * 2 * size;
+            let sorted = [&blue, &green, &red];
+            for y in 0..2 {
+                let offset = u32_at(&exr, at + y * 8) as usize;
+                assert_eq!(offset, at + 16 + y * line_size);
+                assert_eq!(u32_at(&exr, offset) as usize, y);
+                assert_eq!(u32_at(&exr, offset + 4) as usize, line_size - 8);
+
+                let values = &exr[offset + 8..offset + line_size];
+                for (c, channel) in sorted.iter().enumerate() {
+                    for x in 0..2 {
+                        let at = (c * 2 + x) * size;
+                        let value = match pixel_type {
+                            PixelType::Half => {
+                                let bits = u16::from_le_bytes([values[at], values[at + 1]]);
+                                f16::from_bits(bits).to_f32()
+                            }
+                            PixelType::Float => f32::from_bits(u32_at(values, at)),
+                        };
+                        assert_eq!(value, channel[y * 2 + x]);
+                    }
+                }
+            }
+            assert_eq!(exr.len(), at + 16 + 2 * line_size);
+        }
+    }
+
+    #[test]
+    fn pfm_rows_go_bottom_up() {
+        let data = (0..12).map(|v| v as f32).collect::<Vec<_>>();
+        let mut pfm = Vec::new();
+        write_pfm(&mut pfm, 2, 2, &data).unwrap();
+
+        let header = b"PF\n2 2\n-1.0\n";
+        assert_eq!(&pfm[..header.len()], header);
+        let values = pfm[header.len()..]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let bottom_up = [6, 7, 8, 9, 10, 11, 0, 1, 2, 3, 4, 5].map(|v| v as f32);
+        assert_eq!(values, bottom_up);
+    }
+}
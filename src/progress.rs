@@ -0,0 +1,256 @@
+use crate::film::Film;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How far a render has got.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progress {
+    /// Pixels that need no more samples, having converged or reached the
+    /// sample count of the render.
+    pub pixels_done: u64,
+    pub pixels: u64,
+    pub samples_done: u64,
+    /// Most samples the render may take, fewer being taken if adaptive
+    /// sampling finds pixels converged early.
+    pub samples: u64,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Fraction of the samples taken so far, between zero and one. It is
+    /// one once every pixel is done, even if adaptive sampling took fewer
+    /// samples than it could have.
+    pub fn fraction(&self) -> f32 {
+        if self.is_finished() {
+            return 1.0;
+        }
+        (self.samples_done as f64 / self.samples.max(1) as f64).min(1.0) as f32
+    }
+
+    /// Estimated time left, assuming the remaining samples are taken at
+    /// the same rate as the ones so far. `None` before the first sample,
+    /// and zero once every pixel is done.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.is_finished() {
+            return Some(Duration::ZERO);
+        }
+        if self.samples_done == 0 {
+            return None;
+        }
+        let left = self.samples.saturating_sub(self.samples_done);
+        Some(self.elapsed.mul_f64(left as f64 / self.samples_done as f64))
+    }
+
+    // Whether every pixel of a render with pixels is done.
+    fn is_finished(&self) -> bool {
+        self.pixels > 0 && self.pixels_done >= self.pixels
+    }
+}
+
+/// Receives the progress of a render, for example to show it to a user.
+///
+/// Methods are called from the rendering threads, one call at a time, and
+/// should return quickly. Both do nothing by default.
+pub trait Observer: Sync {
+    /// Called as tiles are finished.
+    fn progress(&self, _progress: &Progress) {}
+
+    /// Called with the image rendered so far, after each pass of a
+    /// progressive render and once the render is finished.
+    fn preview(&self, _film: &Film) {}
+}
+
+/// An observer ignoring the render.
+impl Observer for () {}
+
+/// Flag asking a render to stop, checked by the rendering threads before
+/// every pixel. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the render to stop. Pixels being sampled are finished first.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Adds up the work done on the tiles of a render, reporting it to an
+/// observer.
+pub struct Tracker<'a, O> {
+    observer: &'a O,
+    start: Instant,
+    state: Mutex<State>,
+}
+
+// Totals so far, with the pixels done in every tile by tile index.
+struct State {
+    progress: Progress,
+    tiles: Vec<u64>,
+}
+
+impl<'a, O: Observer> Tracker<'a, O> {
+    /// Start tracking a render of `pixels` pixels taking up to `samples`
+    /// samples in all.
+    pub fn new(observer: &'a O, pixels: u64, samples: u64) -> Self {
+        Self {
+            observer,
+            start: Instant::now(),
+            state: Mutex::new(State {
+                progress: Progress {
+                    pixels_done: 0,
+                    pixels,
+                    samples_done: 0,
+                    samples,
+                    elapsed: Duration::ZERO,
+                },
+                tiles: Vec::new(),
+            }),
+        }
+    }
+
+    /// Record that a tile took `samples` more samples and now has
+    /// `pixels_done` pixels done, and report the new totals.
+    pub fn update(&self, tile: usize, pixels_done: u64, samples: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.tiles.len() <= tile {
+            state.tiles.resize(tile + 1, 0);
+        }
+        let before = std::mem::replace(&mut state.tiles[tile], pixels_done);
+
+        let progress = &mut state.progress;
+        progress.pixels_done = progress.pixels_done - before + pixels_done;
+        progress.samples_done += samples;
+        progress.elapsed = self.start.elapsed();
+        self.observer.progress(progress);
+    }
+
+    /// Pass the image rendered so far on to the observer.
+    pub fn preview(&self, film: &Film) {
+        let _state = self.state.lock().unwrap();
+        self.observer.preview(film);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::model::Model;
+    use crate::render::tests::with_renderer;
+    use crate::vec3::Vec3;
+
+    // Cancels the render as soon as a tile is reported, counting the
+    // reports.
+    struct CancelOnProgress {
+        cancel: CancelToken,
+        reports: Mutex<u32>,
+    }
+
+    impl Observer for CancelOnProgress {
+        fn progress(&self, _progress: &Progress) {
+            *self.reports.lock().unwrap() += 1;
+            self.cancel.cancel();
+        }
+    }
+
+    // A render cancelled after its first tile stops there, and the image
+    // put together from the tiles keeps that tile.
+    #[test]
+    fn cancelling_keeps_the_tiles_rendered() {
+        let light = Material::diffuse_light(Vec3::ONE);
+        let world = Model::sphere(Vec3::ZERO, 5.0, &light);
+        let observer = CancelOnProgress {
+            cancel: CancelToken::new(),
+            reports: Mutex::new(0),
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+
+        let (frame, first, taken) = with_renderer(&world, 4, |renderer, tiles| {
+            renderer.cancel = observer.cancel.clone();
+            let pixels = renderer.width as u64 * renderer.height as u64;
+            let tracker = Tracker::new(&observer, pixels, pixels * 4);
+            let mut rendered = renderer.start(tiles);
+            let taken = pool.install(|| renderer.refine(&mut rendered, 4, None, &tracker));
+            (renderer.assemble(&rendered), tiles[0].clone(), taken)
+        });
+        assert_eq!(*observer.reports.lock().unwrap(), 1);
+        assert_eq!(taken, first.pixels().count() as u64 * 4);
+
+        let (width, height) = (frame.film.width(), frame.film.height());
+        for y in 0..height {
+            for x in 0..width {
+                let inside = first.x.contains(&x) && first.y.contains(&y);
+                let count = frame.sample_counts.get(x, y).x;
+                assert_eq!(count, if inside { 4.0 } else { 0.0 }, "{} {}", x, y);
+            }
+        }
+        let centre = (
+            first.x.start + first.width() / 2,
+            first.y.start + first.height() / 2,
+        );
+        assert_eq!(frame.film.get(centre.0, centre.1), Vec3::ONE);
+        assert_eq!(frame.film.get(width - 1, height - 1), Vec3::ZERO);
+    }
+
+    #[test]
+    fn fraction_and_eta_at_the_start_and_end() {
+        let start = Progress {
+            pixels_done: 0,
+            pixels: 100,
+            samples_done: 0,
+            samples: 400,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(start.fraction(), 0.0);
+        assert_eq!(start.eta(), None);
+
+        let half = Progress {
+            samples_done: 200,
+            elapsed: Duration::from_secs(10),
+            ..start
+        };
+        assert_eq!(half.fraction(), 0.5);
+        assert_eq!(half.eta(), Some(Duration::from_secs(10)));
+
+        let done = Progress {
+            pixels_done: 100,
+            samples_done: 400,
+            elapsed: Duration::from_secs(20),
+            ..start
+        };
+        assert_eq!(done.fraction(), 1.0);
+        assert_eq!(done.eta(), Some(Duration::ZERO));
+
+        // Adaptive sampling may finish every pixel early.
+        let converged = Progress {
+            samples_done: 150,
+            ..done
+        };
+        assert_eq!(converged.fraction(), 1.0);
+        assert_eq!(converged.eta(), Some(Duration::ZERO));
+
+        let empty = Progress {
+            pixels: 0,
+            samples: 0,
+            ..start
+        };
+        assert_eq!(empty.fraction(), 0.0);
+        assert_eq!(empty.eta(), None);
+    }
+}
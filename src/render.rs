@@ -0,0 +1,840 @@
+use crate::adaptive::{AdaptiveSampling, PixelStats};
+use crate::aov::{AovFilm, Aovs, MaterialIds};
+use crate::camera::Camera;
+use crate::debug::{Bounce, Event, TracedPath};
+use crate::film::{Film, SplatFilm};
+use crate::integrator::Integrator;
+use crate::material::{Scatter, Subsurface};
+use crate::model::{Hit, Model};
+use crate::progress::{CancelToken, Observer, Tracker};
+use crate::ray::Ray;
+use crate::sampler::{self, Sampler, SamplerKind};
+use crate::stats::RayStats;
+use crate::tile::Tile;
+use crate::vec3::Vec3;
+
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Everything needed to render an image of a scene.
+pub struct Renderer<'a> {
+    pub camera: &'a Camera,
+    pub world: &'a Model<'a>,
+    pub ids: &'a MaterialIds,
+    pub width: u32,
+    pub height: u32,
+    /// Prototype of the sampler, cloned for every tile.
+    pub sampler: SamplerKind,
+    pub sampling: AdaptiveSampling,
+    /// Empty film covering the image, with the reconstruction filter.
+    pub splats: SplatFilm,
+    /// Most bounces of a path, ending it even where Russian roulette
+    /// would go on.
+    pub max_bounce: i32,
+    /// Bounces after which Russian roulette ends paths at random, with a
+    /// probability rising as their throughput falls. Paths only end at
+    /// `max_bounce` if unset.
+    pub roulette_depth: Option<i32>,
+    /// Largest component of the direct and indirect light of a sample,
+    /// scaling brighter samples down to it. Removes fireflies at the cost
+    /// of darkening the image. Lights seen straight from the camera are
+    /// never clamped.
+    pub clamp_direct: Option<f32>,
+    pub clamp_indirect: Option<f32>,
+    /// Roughness added to the specular scattering of paths with every
+    /// non-specular bounce they make, blurring caustics into less noisy
+    /// light. Specular surfaces stay sharp if unset, and when seen from
+    /// the camera through specular bounces only.
+    pub regularization: Option<f32>,
+    pub integrator: Integrator,
+    /// Stops the render when cancelled, leaving pixels with the samples
+    /// taken so far.
+    pub cancel: CancelToken,
+    /// Rays traced so far, added to as tiles are finished.
+    pub stats: Mutex<RayStats>,
+}
+
+/// Samples taken so far in the pixels of a tile.
+pub struct RenderedTile {
+    pub tile: Tile,
+    // Per-pixel statistics and sums of the samples, in row-major order.
+    pub(crate) stats: Vec<PixelStats>,
+    pub(crate) aovs: Vec<Aovs>,
+    pub(crate) costs: Vec<PixelCost>,
+    // Samples spread by the filter, reaching past the edges of the tile.
+    pub(crate) splats: SplatFilm,
+}
+
+/// Work spent sampling a pixel.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct PixelCost {
+    pub seconds: f32,
+    pub intersection_tests: u64,
+}
+
+/// Measure of the work spent on pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Cost {
+    /// Time spent tracing the paths of the pixel. Varies from one render
+    /// to the next with the load of the machine.
+    Time,
+    /// Ray intersection tests against the primitives of the scene.
+    IntersectionTests,
+}
+
+impl FromStr for Cost {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "time" => Ok(Cost::Time),
+            "tests" | "intersection-tests" => Ok(Cost::IntersectionTests),
+            _ => Err(format!("unknown cost `{}`", s)),
+        }
+    }
+}
+
+/// The image put together from its tiles.
+pub struct Frame {
+    pub film: Film,
+    pub aovs: AovFilm,
+    /// Number of samples taken in every pixel, in all three channels.
+    pub sample_counts: Film,
+    /// Seconds spent in every pixel, in all three channels.
+    pub time: Film,
+    /// Intersection tests done for every pixel, in all three channels.
+    pub intersection_tests: Film,
+}
+
+impl RenderedTile {
+    /// Number of samples taken in the whole tile.
+    pub fn samples(&self) -> u64 {
+        self.stats.iter().map(|stats| stats.count as u64).sum()
+    }
+
+    /// A tile without any samples yet, splatting them on `splats`.
+    pub(crate) fn new(tile: &Tile, splats: SplatFilm) -> Self {
+        let pixels = (tile.width() * tile.height()) as usize;
+        Self {
+            tile: tile.clone(),
+            stats: vec![PixelStats::default(); pixels],
+            aovs: vec![Aovs::default(); pixels],
+            costs: vec![PixelCost::default(); pixels],
+            splats,
+        }
+    }
+}
+
+impl<'a> Renderer<'a> {
+    /// Returns tiles without any samples, ready to be refined.
+    pub fn start(&self, tiles: &[Tile]) -> Vec<RenderedTile> {
+        tiles
+            .iter()
+            .map(|tile| RenderedTile::new(tile, self.splats.around(tile)))
+            .collect()
+    }
+
+    /// Sample every pixel of a tile up to `target` samples, or fewer if
+    /// adaptive sampling finds it converged first. Returns the number of
+    /// samples taken.
+    pub fn refine_tile(&self, rendered: &mut RenderedTile, target: u32) -> u64 {
+        let mut sampler = self.sampler.clone();
+        let mut stats = RayStats::default();
+        let mut taken = 0;
+
+        let pixels = rendered
+            .tile
+            .pixels()
+            .zip(&mut rendered.stats)
+            .zip(&mut rendered.aovs)
+            .zip(&mut rendered.costs);
+        for ((((x, y), pixel), aovs), cost) in pixels {
+            if self.cancel.is_cancelled() {
+                break;
+            }
+            let start = Instant::now();
+            let tests = stats.intersection_tests.total();
+
+            // Keep sampling in batches until the pixel
+            // converges or runs out of budget.
+            let budget = |pixel: &PixelStats| {
+                self.sampling
+                    .next_batch(pixel)
+                    .min(target.saturating_sub(pixel.count))
+            };
+            let mut batch = budget(pixel);
+            while batch > 0 {
+                for _ in 0..batch {
+                    let (position, sample) =
+                        self.sample(&mut sampler, (x, y), pixel.count, &mut stats, &mut |_| {});
+
+                    pixel.add(sample.radiance());
+                    rendered.splats.add_sample(position, sample.radiance());
+                    *aovs = *aovs + sample;
+                }
+                taken += batch as u64;
+                batch = budget(pixel);
+            }
+
+            cost.seconds += start.elapsed().as_secs_f32();
+            cost.intersection_tests += stats.intersection_tests.total() - tests;
+        }
+
+        self.stats.lock().unwrap().merge(&stats);
+        taken
+    }
+
+    /// Trace the paths of a range of samples of a pixel, recording every
+    /// bounce. The paths are the same as the ones taken by the render.
+    pub fn trace_pixel(&self, pixel: (u32, u32), samples: Range<u32>) -> Vec<TracedPath> {
+        let mut sampler = self.sampler.clone();
+        let mut stats = RayStats::default();
+        samples
+            .map(|index| {
+                let mut bounces = Vec::new();
+                let (_, sample) =
+                    self.sample(&mut sampler, pixel, index, &mut stats, &mut |bounce| {
+                        bounces.push(bounce)
+                    });
+                TracedPath {
+                    pixel,
+                    sample: index,
+                    bounces,
+                    radiance: sample.radiance(),
+                }
+            })
+            .collect()
+    }
+
+    // Take a sample of a pixel, returning where it landed on the film
+    // along with what it found, and passing its bounces to `record`.
+    fn sample(
+        &self,
+        sampler: &mut SamplerKind,
+        (x, y): (u32, u32),
+        index: u32,
+        stats: &mut RayStats,
+        record: &mut impl FnMut(Bounce),
+    ) -> ((f32, f32), Aovs) {
+        sampler.start_pixel_sample((x, y), index);
+        let (du, dv) = sampler.next_2d();
+        let position = (x as f32 + du, y as f32 + dv);
+        let ray = self.camera.get_ray(
+            position.0 / self.width as f32,
+            position.1 / self.height as f32,
+            sampler,
+        );
+        let sample = match self.integrator {
+            Integrator::Path => {
+                let (mut sample, seen) = self.color(ray, sampler, stats, record);
+                sample.direct = seen + clamp(sample.direct, self.clamp_direct);
+                sample.indirect = clamp(sample.indirect, self.clamp_indirect);
+                sample
+            }
+            integrator => integrator.shade(ray, self.world, self.ids, sampler, stats),
+        };
+        (position, sample)
+    }
+
+    /// Number of pixels of a tile that need no more samples.
+    pub fn pixels_done(&self, rendered: &RenderedTile) -> u64 {
+        rendered
+            .stats
+            .iter()
+            .filter(|stats| self.sampling.next_batch(stats) == 0)
+            .count() as u64
+    }
+
+    /// Refine the tiles up to `target` samples per pixel on the rayon
+    /// thread pool, handing them out to the workers in order and reporting
+    /// to the tracker as each one is finished.
+    ///
+    /// Tiles not started by the deadline or before the render is cancelled
+    /// are left as they are. Returns the number of samples taken, zero once
+    /// every pixel has converged.
+    pub fn refine(
+        &self,
+        tiles: &mut [RenderedTile],
+        target: u32,
+        deadline: Option<Instant>,
+        tracker: &Tracker<impl Observer>,
+    ) -> u64 {
+        let next = AtomicUsize::new(0);
+        let taken = AtomicU64::new(0);
+        let tiles = tiles.iter_mut().map(Mutex::new).collect::<Vec<_>>();
+
+        rayon::broadcast(|_| loop {
+            if self.cancel.is_cancelled()
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                break;
+            }
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let mut rendered = match tiles.get(index) {
+                Some(tile) => tile.lock().unwrap(),
+                None => break,
+            };
+            let samples = self.refine_tile(&mut rendered, target);
+            taken.fetch_add(samples, Ordering::Relaxed);
+            tracker.update(rendered.tile.index, self.pixels_done(&rendered), samples);
+        });
+
+        taken.into_inner()
+    }
+
+    /// Put the image together from rendered tiles.
+    ///
+    /// Depth images are divided by their largest depth, leaving them
+    /// between zero and one.
+    ///
+    /// Tiles are merged in their rendering order, so that the samples
+    /// they share at their edges are always summed in the same order,
+    /// however the tiles were scheduled.
+    pub fn assemble(&self, tiles: &[RenderedTile]) -> Frame {
+        let mut splats = self.splats.clone();
+        let mut aovs = AovFilm::new(self.width, self.height);
+        let mut sample_counts = Film::new(self.width, self.height);
+        let mut time = Film::new(self.width, self.height);
+        let mut intersection_tests = Film::new(self.width, self.height);
+
+        let mut ordered = tiles.iter().collect::<Vec<_>>();
+        ordered.sort_by_key(|rendered| rendered.tile.index);
+        for rendered in ordered {
+            splats.merge(&rendered.splats);
+            for ((((x, y), stats), sum), cost) in rendered
+                .tile
+                .pixels()
+                .zip(&rendered.stats)
+                .zip(&rendered.aovs)
+                .zip(&rendered.costs)
+            {
+                aovs.set(x, y, *sum / stats.count.max(1) as f32);
+                sample_counts.set(x, y, Vec3::all(stats.count as f32));
+                time.set(x, y, Vec3::all(cost.seconds));
+                intersection_tests.set(x, y, Vec3::all(cost.intersection_tests as f32));
+            }
+        }
+
+        let mut film = splats.to_film();
+        if self.integrator == Integrator::Depth {
+            let far = film
+                .pixels()
+                .iter()
+                .fold(0f32, |far, depth| far.max(depth.x));
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    film.set(x, y, film.get(x, y) / far.max(f32::MIN_POSITIVE));
+                }
+            }
+        }
+
+        Frame {
+            film,
+            aovs,
+            sample_counts,
+            time,
+            intersection_tests,
+        }
+    }
+
+    // Trace the path of a camera ray, passing its bounces to `record`.
+    // Returns the light seen straight from the camera apart from the rest
+    // of the direct light, so that it can be left unclamped.
+    fn color(
+        &self,
+        mut ray: Ray,
+        sampler: &mut impl Sampler,
+        stats: &mut RayStats,
+        record: &mut impl FnMut(Bounce),
+    ) -> (Aovs, Vec3) {
+        let (world, ids) = (self.world, self.ids);
+        let mut factor = Vec3::ONE;
+        let mut aovs = Aovs::default();
+        let mut seen = Vec3::ZERO;
+        let mut bounces = 0;
+        // The participating medium the ray is currently travelling through.
+        let mut medium: Option<&Subsurface> = None;
+        // Whether the path was cut short by the bounce limit.
+        let mut cut = false;
+        // Roughness that regularization adds to specular scattering.
+        let mut roughness = 0.0;
+
+        while let Some(rec) = trace(world, &ray, bounces, stats) {
+            if bounces == 0 {
+                aovs.record_first_hit(&ray, &rec, ids);
+            }
+
+            // Maximum number of bounces. If exceeded, return the
+            // result of all interactions so far with the scene.
+            if bounces >= self.max_bounce {
+                record(Bounce {
+                    origin: ray.origin,
+                    direction: ray.direction,
+                    point: Some(rec.point),
+                    event: Event::Cut,
+                    normal: rec.normal,
+                    material: Some(rec.material.name()),
+                    throughput: factor,
+                    attenuation: Vec3::ZERO,
+                    emitted: Vec3::ZERO,
+                });
+                cut = true;
+                break;
+            }
+
+            // Inside a medium, the ray may scatter before it
+            // reaches the boundary.
+            if let Some(m) = medium {
+                let parameter = m.sample_distance(sampler) / ray.direction.mag();
+                if parameter < rec.parameter {
+                    let point = ray.point_at_parameter(parameter);
+                    let Scatter {
+                        scattered,
+                        attenuation,
+                    } = m.scatter_volume(ray, point, sampler);
+                    let survival = self.survival(factor * attenuation, bounces, sampler);
+                    record(Bounce {
+                        origin: ray.origin,
+                        direction: ray.direction,
+                        point: Some(point),
+                        event: if survival == 0.0 {
+                            Event::Roulette
+                        } else {
+                            Event::Medium
+                        },
+                        normal: Vec3::ZERO,
+                        material: None,
+                        throughput: factor,
+                        attenuation: if survival == 0.0 {
+                            Vec3::ZERO
+                        } else {
+                            attenuation / survival
+                        },
+                        emitted: Vec3::ZERO,
+                    });
+                    if survival == 0.0 {
+                        stats.end_path(bounces as usize);
+                        return (aovs, seen);
+                    }
+                    ray = scattered;
+                    factor *= attenuation / survival;
+                    bounces += 1;
+                    continue;
+                }
+            }
+
+            // Light emitted by the object towards the camera, dimmed by
+            // all of the interactions of the path so far.
+            let emission = rec.material.emit(ray, rec);
+            let emitted = factor * emission;
+            if bounces == 0 {
+                seen += emitted;
+            } else if bounces == 1 {
+                aovs.direct += emitted;
+            } else {
+                aovs.indirect += emitted;
+            }
+
+            // Get the scattering result from interacting with
+            // the material of the object.
+            let Scatter {
+                mut scattered,
+                attenuation,
+            } = rec.material.scatter(ray, &rec, sampler);
+
+            // Regularization blurs the specular scattering of paths that
+            // already made non-specular bounces, more so with every one.
+            if let Some(step) = self.regularization {
+                if !rec.material.is_specular() {
+                    roughness = (roughness + step).min(1.0);
+                } else if roughness > 0.0 && scattered != Ray::ZERO {
+                    scattered = roughen(&rec, scattered, roughness, sampler);
+                }
+            }
+
+            // If the ray is completely absorbed, then no more
+            // light can reach the camera through this path.
+            let absorbed = scattered == Ray::ZERO || attenuation == Vec3::ZERO;
+            // Otherwise, Russian roulette may end it, making up for the
+            // paths it ends by brightening the ones it keeps.
+            let survival = if absorbed {
+                1.0
+            } else {
+                self.survival(factor * attenuation, bounces, sampler)
+            };
+            record(Bounce {
+                origin: ray.origin,
+                direction: ray.direction,
+                point: Some(rec.point),
+                event: if absorbed {
+                    Event::Absorbed
+                } else if survival == 0.0 {
+                    Event::Roulette
+                } else {
+                    Event::Scattered
+                },
+                normal: rec.normal,
+                material: Some(rec.material.name()),
+                throughput: factor,
+                attenuation: if survival == 0.0 {
+                    Vec3::ZERO
+                } else {
+                    attenuation / survival
+                },
+                emitted: emission,
+            });
+            if absorbed || survival == 0.0 {
+                stats.end_path(bounces as usize);
+                return (aovs, seen);
+            }
+
+            // Entering or leaving a medium changes which one the
+            // ray travels through next.
+            if let Some(m) = rec.material.medium() {
+                let entering = ray.direction.dot(rec.normal) < 0.0;
+                let transmitted = (scattered.direction.dot(rec.normal) < 0.0) == entering;
+                if transmitted {
+                    medium = if entering { Some(m) } else { None };
+                }
+            }
+
+            ray = scattered;
+            factor *= attenuation / survival;
+            bounces += 1;
+        }
+
+        // let unit_direction = ray.direction.unit();
+        // let t = 0.5 * (unit_direction.y + 1.0);
+        // let sky_color = (1.0 - t) * Vec3::ID + t * vec3(0.5, 0.7, 1.0);
+        let sky_color = Vec3::ZERO;
+        stats.end_path(bounces as usize);
+        if !cut {
+            record(Bounce {
+                origin: ray.origin,
+                direction: ray.direction,
+                point: None,
+                event: Event::Escaped,
+                normal: Vec3::ZERO,
+                material: None,
+                throughput: factor,
+                attenuation: Vec3::ZERO,
+                emitted: sky_color,
+            });
+        }
+
+        if bounces == 0 {
+            seen += factor * sky_color;
+        } else if bounces == 1 {
+            aovs.direct += factor * sky_color;
+        } else {
+            aovs.indirect += factor * sky_color;
+        }
+
+        (aovs, seen)
+    }
+
+    // Probability of a path going on after bounce number `bounces`, with
+    // the throughput it would go on with, or zero if Russian roulette ends
+    // it. Paths with a throughput of one or more always go on.
+    fn survival(&self, throughput: Vec3, bounces: i32, sampler: &mut impl Sampler) -> f32 {
+        match self.roulette_depth {
+            Some(depth) if bounces >= depth => {
+                let probability = throughput.max_component().min(1.0);
+                if sampler.next_1d() < probability {
+                    probability
+                } else {
+                    0.0
+                }
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+// Scale radiance down so that its largest component is at most `limit`,
+// if there is one.
+fn clamp(radiance: Vec3, limit: Option<f32>) -> Vec3 {
+    match limit {
+        Some(limit) if radiance.max_component() > limit => {
+            radiance * (limit / radiance.max_component())
+        }
+        _ => radiance,
+    }
+}
+
+// Perturb a ray scattered off a surface within a ball of radius
+// `roughness`, keeping it unchanged if it would cross to the other side.
+fn roughen(rec: &Hit, scattered: Ray, roughness: f32, sampler: &mut impl Sampler) -> Ray {
+    let direction = scattered.direction.normalize()
+        + sampler::unit_ball(sampler.next_2d(), sampler.next_1d()) * roughness;
+    let side = |direction: Vec3| direction.dot(rec.normal) > 0.0;
+    if side(direction) == side(scattered.direction) {
+        rec.spawn_ray(direction)
+    } else {
+        scattered
+    }
+}
+
+// Find the closest hit along a ray, counting it in the statistics.
+pub(crate) fn trace<'a>(
+    world: &'a Model,
+    ray: &Ray,
+    bounces: i32,
+    stats: &mut RayStats,
+) -> Option<Hit<'a>> {
+    if bounces == 0 {
+        stats.camera_rays += 1;
+    } else {
+        stats.secondary_rays += 1;
+    }
+    world.hit_counting(ray, 0.00001, f32::MAX, &mut stats.intersection_tests)
+}
+
+// Find the closest hit along a ray only checking for what lies in the
+// way, counting it as a shadow ray.
+pub(crate) fn trace_shadow<'a>(
+    world: &'a Model,
+    ray: &Ray,
+    stats: &mut RayStats,
+) -> Option<Hit<'a>> {
+    stats.shadow_rays += 1;
+    world.hit_counting(ray, 0.00001, f32::MAX, &mut stats.intersection_tests)
+}
+
+/// Sample counts of the passes of a progressive render, doubling up to
+/// the given number of samples per pixel.
+pub fn progressive_passes(samples: u32) -> Vec<u32> {
+    let mut passes = Vec::new();
+    let mut target = 1;
+    while target < samples {
+        passes.push(target);
+        target *= 2;
+    }
+    passes.push(samples);
+    passes
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::filter::Filter;
+    use crate::material::Material;
+    use crate::tile::{self, Tile, TileOrder};
+    use crate::vec3::vec3;
+
+    /// Run `f` with a renderer of a small image of a scene, seen from the
+    /// origin looking down `-z`, and the tiles of the image.
+    pub(crate) fn with_renderer<T>(
+        world: &Model,
+        samples: u32,
+        f: impl FnOnce(&mut Renderer, &[Tile]) -> T,
+    ) -> T {
+        let (width, height) = (24, 16);
+        let camera = Camera::new(
+            Vec3::ZERO,
+            vec3(0.0, 0.0, -1.0),
+            vec3(0.0, 1.0, 0.0),
+            60.0,
+            width as f32 / height as f32,
+            0.0,
+            1.0,
+        );
+        let ids = MaterialIds::new(world);
+        let mut renderer = Renderer {
+            camera: &camera,
+            world,
+            ids: &ids,
+            width,
+            height,
+            sampler: SamplerKind::from_name("independent", samples, 0).unwrap(),
+            sampling: AdaptiveSampling::fixed(samples),
+            splats: SplatFilm::new(width, height, Filter::Box, 0.5),
+            max_bounce: 50,
+            roulette_depth: None,
+            clamp_direct: None,
+            clamp_indirect: None,
+            regularization: None,
+            integrator: Integrator::Path,
+            cancel: CancelToken::new(),
+            stats: Default::default(),
+        };
+        let tiles = tile::tiles(width, height, 8, TileOrder::default());
+        f(&mut renderer, &tiles)
+    }
+
+    // Render the image of `with_renderer`, with the renderer adjusted by
+    // `configure`.
+    fn render(world: &Model, samples: u32, configure: impl FnOnce(&mut Renderer)) -> Film {
+        with_renderer(world, samples, |renderer, tiles| {
+            configure(renderer);
+            let mut rendered = renderer.start(tiles);
+            renderer.refine(&mut rendered, samples, None, &Tracker::new(&(), 0, 0));
+            renderer.assemble(&rendered).film
+        })
+    }
+
+    // Emission is evaluated for the ray arriving at the light, so the
+    // inside of a one-sided light stays dark.
+    #[test]
+    fn one_sided_light_is_dark_from_behind() {
+        let one_sided = Material::blackbody_light(6500.0, 1.0);
+        let two_sided = Material::diffuse_light(Vec3::ONE);
+        let around = |material| Model::sphere(Vec3::ZERO, 5.0, material);
+        let ahead = |material| Model::sphere(vec3(0.0, 0.0, -3.0), 2.0, material);
+
+        let inside = render(&around(&one_sided), 2, |_| {});
+        assert!(inside.pixels().iter().all(|&pixel| pixel == Vec3::ZERO));
+        let inside = render(&around(&two_sided), 2, |_| {});
+        assert!(inside.pixels().iter().all(|&pixel| pixel == Vec3::ONE));
+        let front = render(&ahead(&one_sided), 2, |_| {});
+        assert!(front.get(12, 8).mag() > 0.5);
+    }
+
+    // Clamping tames the light a sample brings off surfaces, but leaves
+    // lights seen straight from the camera as bright as they are.
+    #[test]
+    fn clamping_leaves_lights_seen_from_the_camera() {
+        let ground = Material::lambertian(vec3(0.5, 0.5, 0.5));
+        let light = Material::diffuse_light(Vec3::all(20.0));
+        let world = Model::list(vec![
+            Model::sphere(vec3(0.0, -101.5, -3.0), 100.0, &ground),
+            Model::sphere(vec3(0.0, 0.0, -3.0), 1.0, &light),
+        ]);
+
+        let unclamped = render(&world, 4, |_| {});
+        let clamped = render(&world, 4, |renderer| {
+            renderer.clamp_direct = Some(0.5);
+            renderer.clamp_indirect = Some(0.5);
+        });
+        assert_eq!(clamped.get(12, 8), Vec3::all(20.0));
+        assert!(unclamped.get(12, 15).max_component() > 1.0);
+        assert!(clamped.get(12, 15).max_component() <= 1.0);
+    }
+
+    // Regularization only blurs specular bounces after non-specular ones,
+    // so mirrors seen from the camera stay sharp while their reflections
+    // in diffuse surfaces blur.
+    #[test]
+    fn regularization_follows_non_specular_bounces() {
+        let ground = Material::lambertian(vec3(0.5, 0.5, 0.5));
+        let mirror = Material::metal(Vec3::ONE, 0.0);
+        let light = Material::diffuse_light(Vec3::all(4.0));
+        let mirror_and_light = || {
+            vec![
+                Model::sphere(vec3(0.0, 0.0, -3.0), 1.0, &mirror),
+                Model::sphere(vec3(0.0, 2.5, -3.0), 1.0, &light),
+            ]
+        };
+        let regularize = |renderer: &mut Renderer| renderer.regularization = Some(0.5);
+
+        let world = Model::list(mirror_and_light());
+        let sharp = render(&world, 4, |_| {});
+        assert!(sharp.pixels().iter().any(|&pixel| pixel != Vec3::ZERO));
+        assert_eq!(render(&world, 4, regularize).pixels(), sharp.pixels());
+
+        let mut models = mirror_and_light();
+        models.push(Model::sphere(vec3(0.0, -101.0, -3.0), 100.0, &ground));
+        let world = Model::list(models);
+        let sharp = render(&world, 4, |_| {});
+        assert_ne!(render(&world, 4, regularize).pixels(), sharp.pixels());
+    }
+
+    // Russian roulette brightens the paths it keeps to make up for the
+    // ones it ends, so the image is as bright on average as without it.
+    #[test]
+    fn russian_roulette_is_unbiased() {
+        // Light bounces many times between the ground and a pale ball
+        // before reaching the camera, giving roulette paths to end.
+        let pale = Material::lambertian(Vec3::all(0.8));
+        let light = Material::diffuse_light(Vec3::all(2.0));
+        let world = Model::list(vec![
+            Model::sphere(vec3(0.0, -101.0, -3.0), 100.0, &pale),
+            Model::sphere(vec3(0.0, 0.0, -3.0), 1.0, &pale),
+            Model::sphere(vec3(0.0, 8.0, -3.0), 4.0, &light),
+        ]);
+        let mean = |film: &Film| {
+            let sum = film
+                .pixels()
+                .iter()
+                .fold(Vec3::ZERO, |sum, &pixel| sum + pixel);
+            sum / film.pixels().len() as f32
+        };
+
+        let full = mean(&render(&world, 256, |_| {}));
+        let roulette = mean(&render(&world, 256, |renderer| {
+            renderer.roulette_depth = Some(1)
+        }));
+        assert!(full.x > 0.05, "{:?}", full);
+        assert!(
+            (roulette.x / full.x - 1.0).abs() < 0.02,
+            "{:?} {:?}",
+            roulette,
+            full
+        );
+    }
+
+    // Tiles are handed to whichever thread is free, so the image must not
+    // depend on the number of threads, adaptive sampling and splats
+    // included.
+    #[test]
+    fn image_does_not_depend_on_thread_count() {
+        let ground = Material::lambertian(vec3(0.5, 0.5, 0.5));
+        let glass = Material::dielectric(1.5);
+        let light = Material::diffuse_light(Vec3::all(4.0));
+        let world = Model::list(vec![
+            Model::sphere(vec3(0.0, -101.0, -3.0), 100.0, &ground),
+            Model::sphere(vec3(-0.6, 0.0, -3.0), 0.8, &glass),
+            Model::sphere(vec3(1.0, 1.5, -3.5), 0.5, &light),
+        ]);
+
+        let render_with = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| {
+                render(&world, 16, |renderer| {
+                    renderer.sampler = SamplerKind::from_name("sobol", 16, 3).unwrap();
+                    renderer.sampling = AdaptiveSampling::new(4, 16, 0.05);
+                    renderer.splats = SplatFilm::new(
+                        renderer.width,
+                        renderer.height,
+                        Filter::Mitchell,
+                        Filter::Mitchell.default_radius(),
+                    );
+                })
+            })
+        };
+        let single = render_with(1);
+        assert!(single.pixels().iter().any(|&pixel| pixel != Vec3::ZERO));
+        assert_eq!(single.pixels(), render_with(4).pixels());
+    }
+
+    // Ambient occlusion traces one shadow ray from every surface the
+    // camera sees, and none for rays leaving the scene.
+    #[test]
+    fn ambient_occlusion_counts_shadow_rays() {
+        let ground = Material::lambertian(vec3(0.5, 0.5, 0.5));
+        let stats = |world: &Model| {
+            with_renderer(world, 2, |renderer, tiles| {
+                renderer.integrator = Integrator::AmbientOcclusion { distance: 1.0 };
+                let mut rendered = renderer.start(tiles);
+                renderer.refine(&mut rendered, 2, None, &Tracker::new(&(), 0, 0));
+                renderer.stats.lock().unwrap().clone()
+            })
+        };
+
+        let around = stats(&Model::sphere(Vec3::ZERO, 5.0, &ground));
+        assert_eq!(around.camera_rays, 24 * 16 * 2);
+        assert_eq!(around.shadow_rays, around.camera_rays);
+        assert_eq!(around.secondary_rays, 0);
+        assert_eq!(around.rays(), around.camera_rays * 2);
+
+        let empty = stats(&Model::list(Vec::new()));
+        assert_eq!(empty.camera_rays, 24 * 16 * 2);
+        assert_eq!(empty.shadow_rays, 0);
+    }
+}
@@ -0,0 +1,698 @@
+use crate::vec3::{vec3, Vec3};
+
+use std::f32::consts::PI;
+use std::sync::OnceLock;
+
+/// Source of the random numbers used to trace a path.
+///
+/// Every call hands out the next dimension of the current sample, so
+/// that low-discrepancy samplers can spread the samples of a pixel
+/// evenly in each dimension.
+pub trait Sampler {
+    /// Start generating sample `index` of the given pixel.
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32);
+
+    /// Returns the next dimension of the sample, in `[0, 1)`.
+    fn next_1d(&mut self) -> f32;
+
+    /// Returns the next two dimensions of the sample, in `[0, 1)`.
+    fn next_2d(&mut self) -> (f32, f32) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+// Sampler enum so we can avoid dynamic dispatch.
+/// Sampler enumeration, to pick an implementation at run time.
+#[derive(Debug, Clone)]
+pub enum SamplerKind {
+    Independent(Independent),
+    Stratified(Stratified),
+    Halton(Halton),
+    Sobol(Sobol),
+    BlueNoise(BlueNoise),
+}
+
+impl SamplerKind {
+    /// Names accepted by [`SamplerKind::from_name`].
+    pub const NAMES: [&'static str; 5] =
+        ["independent", "stratified", "halton", "sobol", "blue-noise"];
+
+    /// Construct a sampler by name, for renders taking `samples` samples
+    /// per pixel. Renders with the same seed use the same numbers.
+    pub fn from_name(name: &str, samples: u32, seed: u32) -> Option<Self> {
+        match name {
+            "independent" => Some(Self::Independent(Independent::new(seed))),
+            "stratified" => Some(Self::Stratified(Stratified::new(samples, seed))),
+            "halton" => Some(Self::Halton(Halton::new(seed))),
+            "sobol" => Some(Self::Sobol(Sobol::new(seed))),
+            "blue-noise" => Some(Self::BlueNoise(BlueNoise::new(seed))),
+            _ => None,
+        }
+    }
+}
+
+impl Sampler for SamplerKind {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        match self {
+            SamplerKind::Independent(s) => s.start_pixel_sample(pixel, index),
+            SamplerKind::Stratified(s) => s.start_pixel_sample(pixel, index),
+            SamplerKind::Halton(s) => s.start_pixel_sample(pixel, index),
+            SamplerKind::Sobol(s) => s.start_pixel_sample(pixel, index),
+            SamplerKind::BlueNoise(s) => s.start_pixel_sample(pixel, index),
+        }
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        match self {
+            SamplerKind::Independent(s) => s.next_1d(),
+            SamplerKind::Stratified(s) => s.next_1d(),
+            SamplerKind::Halton(s) => s.next_1d(),
+            SamplerKind::Sobol(s) => s.next_1d(),
+            SamplerKind::BlueNoise(s) => s.next_1d(),
+        }
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        match self {
+            SamplerKind::Independent(s) => s.next_2d(),
+            SamplerKind::Stratified(s) => s.next_2d(),
+            SamplerKind::Halton(s) => s.next_2d(),
+            SamplerKind::Sobol(s) => s.next_2d(),
+            SamplerKind::BlueNoise(s) => s.next_2d(),
+        }
+    }
+}
+
+/// Uniform random numbers with no correlation between samples.
+#[derive(Debug, Clone, Default)]
+pub struct Independent {
+    seed: u32,
+    rng: Rng,
+}
+
+impl Independent {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+}
+
+impl Sampler for Independent {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        self.rng.next_f32()
+    }
+}
+
+/// Jittered samples, where every dimension of a pixel is split into as
+/// many strata as there are samples and each sample lands in its own
+/// stratum. Pairs of dimensions are stratified together on a grid.
+#[derive(Debug, Clone)]
+pub struct Stratified {
+    samples: u32,
+    // Cells along each side of the grid used for pairs of dimensions.
+    grid: u32,
+    seed: u32,
+    key: u32,
+    index: u32,
+    dimension: u32,
+    // Jitter within the strata.
+    rng: Rng,
+}
+
+impl Stratified {
+    pub fn new(samples: u32, seed: u32) -> Self {
+        let samples = samples.max(1);
+        Self {
+            samples,
+            grid: (samples as f32).sqrt().ceil() as u32,
+            seed,
+            key: 0,
+            index: 0,
+            dimension: 0,
+            rng: Rng::default(),
+        }
+    }
+}
+
+impl Sampler for Stratified {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.key = pixel_key(self.seed, pixel);
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        // Shuffle the strata differently in every dimension, so
+        // that dimensions are not correlated with each other.
+        let key = hash(self.key ^ hash(self.dimension));
+        self.dimension += 1;
+        let stratum = permute(self.index % self.samples, self.samples, key);
+        (stratum as f32 + self.rng.next_f32()) / self.samples as f32
+    }
+
+    fn next_2d(&mut self) -> (f32, f32) {
+        let key = hash(self.key ^ hash(self.dimension));
+        self.dimension += 2;
+        let cells = self.grid * self.grid;
+        let cell = permute(self.index % cells, cells, key);
+        (
+            ((cell % self.grid) as f32 + self.rng.next_f32()) / self.grid as f32,
+            ((cell / self.grid) as f32 + self.rng.next_f32()) / self.grid as f32,
+        )
+    }
+}
+
+/// The Halton sequence, using the radical inverse in a different prime
+/// base for every dimension, randomly shifted per pixel.
+#[derive(Debug, Clone, Default)]
+pub struct Halton {
+    seed: u32,
+    key: u32,
+    index: u32,
+    dimension: usize,
+    // Numbers for the dimensions past the table of primes.
+    rng: Rng,
+}
+
+impl Halton {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+}
+
+impl Sampler for Halton {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.key = pixel_key(self.seed, pixel);
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension) {
+            // Cranley-Patterson rotation to decorrelate pixels.
+            Some(&base) => {
+                let shift = to_unit(hash(self.key ^ hash(dimension as u32)));
+                fract(radical_inverse(base, self.index) + shift)
+            }
+            // The sequence degrades in high dimensions anyway.
+            None => self.rng.next_f32(),
+        }
+    }
+}
+
+/// The Sobol sequence with hash-based Owen scrambling, after Burley
+/// (2020), "Practical Hash-based Owen Scrambling".
+///
+/// Dimensions are generated in padded groups of four, with the sample
+/// order shuffled independently for each group.
+#[derive(Debug, Clone, Default)]
+pub struct Sobol {
+    seed: u32,
+    key: u32,
+    index: u32,
+    dimension: u32,
+}
+
+impl Sobol {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+}
+
+impl Sampler for Sobol {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.key = pixel_key(self.seed, pixel);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let group = self.dimension / 4;
+        let within = (self.dimension % 4) as usize;
+        self.dimension += 1;
+
+        let group_seed = hash(self.key ^ hash(group));
+        let index = nested_uniform_scramble(self.index, group_seed);
+        let value = sobol(index, within);
+        to_unit(nested_uniform_scramble(
+            value,
+            hash(group_seed ^ hash(within as u32 + 1)),
+        ))
+    }
+}
+
+/// Samples that distribute the error between pixels as blue noise, by
+/// shifting the Halton sequence with a blue noise mask tiled over the
+/// image.
+#[derive(Debug, Clone, Default)]
+pub struct BlueNoise {
+    seed: u32,
+    pixel: (u32, u32),
+    index: u32,
+    dimension: usize,
+    rng: Rng,
+}
+
+impl BlueNoise {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+}
+
+impl Sampler for BlueNoise {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.rng = Rng::for_sample(self.seed, pixel, index);
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match PRIMES.get(dimension) {
+            Some(&base) => {
+                // Offset the mask differently in every dimension.
+                let offset = hash(self.seed ^ hash(dimension as u32));
+                // Reduce before adding, as pixels and offsets take every u32.
+                let wrap = |a: u32, b: u32| {
+                    (a as usize % BLUE_NOISE_SIZE + b as usize % BLUE_NOISE_SIZE) % BLUE_NOISE_SIZE
+                };
+                let x = wrap(self.pixel.0, offset);
+                let y = wrap(self.pixel.1, offset >> 16);
+                let shift = blue_noise_mask()[y * BLUE_NOISE_SIZE + x];
+                fract(radical_inverse(base, self.index) + shift)
+            }
+            None => self.rng.next_f32(),
+        }
+    }
+}
+
+/// Map two uniform numbers to a point uniformly distributed in the
+/// unit disk on the xy plane.
+pub fn unit_disk((u1, u2): (f32, f32)) -> Vec3 {
+    let theta = u1 * 2.0 * PI;
+    let r = u2.sqrt();
+    vec3(r * theta.cos(), r * theta.sin(), 0.0)
+}
+
+/// Map three uniform numbers to a point uniformly distributed in the
+/// unit ball.
+pub fn unit_ball((u1, u2): (f32, f32), u3: f32) -> Vec3 {
+    let theta = u1 * 2.0 * PI;
+    let cos_phi = 2.0 * u2 - 1.0;
+    let sin_phi = (1.0 - cos_phi * cos_phi).max(0.0).sqrt();
+    let r = u3.cbrt();
+    vec3(
+        r * sin_phi * theta.cos(),
+        r * sin_phi * theta.sin(),
+        r * cos_phi,
+    )
+}
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Mirror the digits of `index` in the given base around the radix point.
+fn radical_inverse(base: u32, mut index: u32) -> f32 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv = inv_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * inv;
+        index /= base;
+        inv *= inv_base;
+    }
+    (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Direction numbers of the first four Sobol dimensions, from the
+// primitive polynomials and initial numbers of Joe and Kuo (2008).
+fn sobol_directions() -> &'static [[u32; 32]; 4] {
+    static DIRECTIONS: OnceLock<[[u32; 32]; 4]> = OnceLock::new();
+    DIRECTIONS.get_or_init(|| {
+        // (degree, coefficients, initial direction numbers)
+        let polynomials: [(usize, u32, &[u32]); 3] =
+            [(1, 0, &[1]), (2, 1, &[1, 3]), (3, 1, &[1, 3, 1])];
+
+        let mut directions = [[0; 32]; 4];
+        for (k, v) in directions[0].iter_mut().enumerate() {
+            *v = 1 << (31 - k);
+        }
+        for (d, &(s, a, m)) in polynomials.iter().enumerate() {
+            let v = &mut directions[d + 1];
+            for k in 0..32 {
+                v[k] = if k < s {
+                    m[k] << (31 - k)
+                } else {
+                    let mut value = v[k - s] ^ (v[k - s] >> s);
+                    for j in 1..s {
+                        if (a >> (s - 1 - j)) & 1 == 1 {
+                            value ^= v[k - j];
+                        }
+                    }
+                    value
+                };
+            }
+        }
+        directions
+    })
+}
+
+// Sample `index` of the given Sobol dimension, as a 32-bit fraction.
+fn sobol(mut index: u32, dimension: usize) -> u32 {
+    let directions = &sobol_directions()[dimension];
+    let mut result = 0;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            result ^= directions[bit];
+        }
+        index >>= 1;
+        bit += 1;
+    }
+    result
+}
+
+// Owen scrambling of the bits of `x`, from most to least significant.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Hash where every bit only depends on the bits below it.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x
+}
+
+// Side of the tiled blue noise mask.
+const BLUE_NOISE_SIZE: usize = 64;
+
+// Blue noise mask with values evenly spread over `[0, 1)`, generated
+// with the void-and-cluster method of Ulichney (1993) the first time
+// it is needed.
+fn blue_noise_mask() -> &'static [f32] {
+    static MASK: OnceLock<Vec<f32>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        const SIZE: usize = BLUE_NOISE_SIZE;
+        const SIGMA: f32 = 1.5;
+        let n = SIZE * SIZE;
+
+        // Gaussian falloff for every offset on the torus.
+        let mut kernel = vec![0.0; n];
+        for dy in 0..SIZE {
+            for dx in 0..SIZE {
+                let x = dx.min(SIZE - dx) as f32;
+                let y = dy.min(SIZE - dy) as f32;
+                kernel[dy * SIZE + dx] = (-(x * x + y * y) / (2.0 * SIGMA * SIGMA)).exp();
+            }
+        }
+
+        let mut ones = vec![false; n];
+        let mut energy = vec![0.0f32; n];
+        let toggle = |ones: &mut Vec<bool>, energy: &mut Vec<f32>, p: usize| {
+            ones[p] = !ones[p];
+            let sign = if ones[p] { 1.0 } else { -1.0 };
+            let (px, py) = (p % SIZE, p / SIZE);
+            for (q, e) in energy.iter_mut().enumerate() {
+                let dx = (q % SIZE + SIZE - px) % SIZE;
+                let dy = (q / SIZE + SIZE - py) % SIZE;
+                *e += sign * kernel[dy * SIZE + dx];
+            }
+        };
+        let tightest_cluster = |ones: &[bool], energy: &[f32]| {
+            (0..n)
+                .filter(|&p| ones[p])
+                .max_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .unwrap()
+        };
+        let largest_void = |ones: &[bool], energy: &[f32]| {
+            (0..n)
+                .filter(|&p| !ones[p])
+                .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .unwrap()
+        };
+
+        // Scatter a few points, then move points from the tightest
+        // cluster to the largest void until the pattern settles.
+        let initial = n / 10;
+        let mut state = 0;
+        let mut count = 0;
+        while count < initial {
+            state += 1;
+            let p = hash(state) as usize % n;
+            if !ones[p] {
+                toggle(&mut ones, &mut energy, p);
+                count += 1;
+            }
+        }
+        loop {
+            let cluster = tightest_cluster(&ones, &energy);
+            toggle(&mut ones, &mut energy, cluster);
+            let void = largest_void(&ones, &energy);
+            toggle(&mut ones, &mut energy, void);
+            if void == cluster {
+                break;
+            }
+        }
+
+        // Rank the initial points by removing the tightest clusters
+        // first, then rank the rest by filling the largest voids.
+        let mut rank = vec![0; n];
+        let (prototype, prototype_energy) = (ones.clone(), energy.clone());
+        for r in (0..initial).rev() {
+            let cluster = tightest_cluster(&ones, &energy);
+            toggle(&mut ones, &mut energy, cluster);
+            rank[cluster] = r;
+        }
+        ones = prototype;
+        energy = prototype_energy;
+        for r in initial..n {
+            let void = largest_void(&ones, &energy);
+            toggle(&mut ones, &mut energy, void);
+            rank[void] = r;
+        }
+
+        rank.into_iter()
+            .map(|r| (r as f32 + 0.5) / n as f32)
+            .collect()
+    })
+}
+
+// Largest float below one.
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+fn fract(x: f32) -> f32 {
+    (x - x.floor()).min(ONE_MINUS_EPSILON)
+}
+
+// Map all 32 bits to a float in `[0, 1)`.
+fn to_unit(x: u32) -> f32 {
+    (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// Integer hash with good avalanche, by Chris Wellons (lowbias32).
+pub(crate) fn hash(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb_352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846c_a68b);
+    x ^= x >> 16;
+    x
+}
+
+// Seed of the hashes and scrambles of a pixel.
+fn pixel_key(seed: u32, (x, y): (u32, u32)) -> u32 {
+    hash(x ^ hash(y ^ hash(seed)))
+}
+
+/// Small and fast random number generator, the PCG32 generator of
+/// O'Neill (2014).
+///
+/// Every sample of every pixel gets its own stream, so the numbers a
+/// sample sees do not depend on the order samples are taken in.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(0x853c_49e6_748f_ea9b, 0xda3e_39cb_94b9_5bdb)
+    }
+}
+
+impl Rng {
+    const MULTIPLIER: u64 = 0x5851_f42d_4c95_7f2d;
+
+    /// Start the given stream at a position picked by `seed`.
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    /// The stream of sample `index` of a pixel, in a render with the
+    /// given seed.
+    ///
+    /// ```
+    /// # use raytracer::sampler::Rng;
+    /// let mut a = Rng::for_sample(7, (3, 4), 0);
+    /// let mut b = Rng::for_sample(7, (3, 4), 0);
+    /// let mut c = Rng::for_sample(7, (3, 4), 1);
+    /// assert_eq!(a.next_u32(), b.next_u32());
+    /// assert_ne!(a.next_u32(), c.next_u32());
+    /// ```
+    pub fn for_sample(seed: u32, (x, y): (u32, u32), index: u32) -> Self {
+        let pixel = ((y as u64) << 32) | x as u64;
+        Self::new(mix(pixel ^ mix(seed as u64)), index as u64)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    /// Returns a number uniformly distributed in `[0, 1)`.
+    pub fn next_f32(&mut self) -> f32 {
+        to_unit(self.next_u32())
+    }
+}
+
+// 64-bit finaliser of SplitMix64.
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// Bijective shuffle of `[0, l)` chosen by `p`, after Kensler (2013),
+// "Correlated Multi-Jittered Sampling".
+fn permute(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Root mean square error over a block of pixels of the `samples`
+    // sample estimate of the area of the quarter disk, `pi / 4`.
+    fn quarter_disk_error(sampler: &mut SamplerKind, samples: u32) -> f32 {
+        const PIXELS: u32 = 16;
+
+        let mut squared = 0.0;
+        for y in 0..PIXELS {
+            for x in 0..PIXELS {
+                let mut inside = 0;
+                for index in 0..samples {
+                    sampler.start_pixel_sample((x, y), index);
+                    let (u, v) = sampler.next_2d();
+                    if u * u + v * v < 1.0 {
+                        inside += 1;
+                    }
+                }
+                let error = inside as f32 / samples as f32 - PI / 4.0;
+                squared += error * error;
+            }
+        }
+        (squared / (PIXELS * PIXELS) as f32).sqrt()
+    }
+
+    #[test]
+    fn low_discrepancy_samplers_converge_faster() {
+        const SAMPLES: u32 = 256;
+
+        let independent = quarter_disk_error(
+            &mut SamplerKind::from_name("independent", SAMPLES, 5).unwrap(),
+            SAMPLES,
+        );
+        for &name in &SamplerKind::NAMES[1..] {
+            let mut sampler = SamplerKind::from_name(name, SAMPLES, 5).unwrap();
+            let error = quarter_disk_error(&mut sampler, SAMPLES);
+            assert!(
+                error < 0.5 * independent,
+                "{} error {} against {} for independent samples",
+                name,
+                error,
+                independent
+            );
+        }
+    }
+
+    #[test]
+    fn blue_noise_takes_any_pixel() {
+        let mut sampler = BlueNoise::new(152_034);
+        sampler.start_pixel_sample((u32::MAX, u32::MAX), 3);
+        for _ in 0..PRIMES.len() + 2 {
+            let u = sampler.next_1d();
+            assert!((0.0..1.0).contains(&u));
+        }
+    }
+}
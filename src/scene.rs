@@ -0,0 +1,226 @@
+use crate::material::Material;
+use crate::model::Model;
+use crate::vec3::{vec3, Vec3};
+
+use std::collections::HashMap;
+use std::str::{FromStr, SplitWhitespace};
+
+/// Where the camera looks from and at, with its vertical field of view
+/// in degrees and the diameter of its lens. It focuses on `look_at`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct View {
+    pub look_from: Vec3,
+    pub look_at: Vec3,
+    pub vfov: f32,
+    pub aperture: f32,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            look_from: vec3(-3.0, 3.0, 2.0),
+            look_at: vec3(0.0, 0.0, -1.0),
+            vfov: 20.0,
+            aperture: 0.0,
+        }
+    }
+}
+
+/// A scene of spheres described in text, one item per line:
+///
+/// ```text
+/// # Comments run to the end of the line.
+/// camera <from x y z> <at x y z> <vfov> [<aperture>]
+/// material <name> lambertian <r g b>
+/// material <name> metal <r g b> <fuzz>
+/// material <name> dielectric <index>
+/// material <name> light <r g b>
+/// sphere <x y z> <radius> <material name>
+/// ```
+///
+/// Materials must be described before the spheres made of them. The
+/// camera has the default view if not given.
+pub struct Scene {
+    pub view: View,
+    materials: Vec<Material<'static>>,
+    // Centre, radius and index of the material of every sphere.
+    spheres: Vec<(Vec3, f32, usize)>,
+}
+
+impl Scene {
+    /// The spheres of the scene, in the order they were described.
+    pub fn model(&self) -> Model<'_> {
+        Model::list(
+            self.spheres
+                .iter()
+                .map(|&(center, radius, material)| {
+                    Model::sphere(center, radius, &self.materials[material])
+                })
+                .collect(),
+        )
+    }
+}
+
+impl FromStr for Scene {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut scene = Scene {
+            view: View::default(),
+            materials: Vec::new(),
+            spheres: Vec::new(),
+        };
+        let mut names = HashMap::new();
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut words = Words(line.split_whitespace());
+            scene
+                .add(&mut words, &mut names)
+                .and_then(|()| words.end())
+                .map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+        Ok(scene)
+    }
+}
+
+impl Scene {
+    // Add the item described by a line, given the indices of the
+    // materials described so far by name.
+    fn add(&mut self, words: &mut Words, names: &mut HashMap<String, usize>) -> Result<(), String> {
+        let item = match words.0.next() {
+            Some(item) => item,
+            None => return Ok(()),
+        };
+        match item {
+            "camera" => {
+                self.view = View {
+                    look_from: words.vec3()?,
+                    look_at: words.vec3()?,
+                    vfov: words.number()?,
+                    aperture: words.optional()?.unwrap_or(0.0),
+                };
+            }
+            "material" => {
+                let name = words.word("a material name")?;
+                if names.contains_key(name) {
+                    return Err(format!("material `{}` is already described", name));
+                }
+                let material = match words.word("a kind of material")? {
+                    "lambertian" => Material::lambertian(words.vec3()?),
+                    "metal" => Material::metal(words.vec3()?, words.number()?),
+                    "dielectric" => Material::dielectric(words.number()?),
+                    "light" => Material::diffuse_light(words.vec3()?),
+                    kind => return Err(format!("unknown material `{}`", kind)),
+                };
+                names.insert(name.to_owned(), self.materials.len());
+                self.materials.push(material);
+            }
+            "sphere" => {
+                let center = words.vec3()?;
+                let radius = words.number()?;
+                let name = words.word("a material name")?;
+                let material = *names
+                    .get(name)
+                    .ok_or_else(|| format!("undescribed material `{}`", name))?;
+                self.spheres.push((center, radius, material));
+            }
+            _ => return Err(format!("unknown item `{}`", item)),
+        }
+        Ok(())
+    }
+}
+
+// The words of a line, read one at a time.
+struct Words<'a>(SplitWhitespace<'a>);
+
+impl<'a> Words<'a> {
+    fn word(&mut self, expected: &str) -> Result<&'a str, String> {
+        self.0
+            .next()
+            .ok_or_else(|| format!("expected {}", expected))
+    }
+
+    fn number(&mut self) -> Result<f32, String> {
+        let word = self.word("a number")?;
+        parse(word)
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, String> {
+        Ok(vec3(self.number()?, self.number()?, self.number()?))
+    }
+
+    // A number that may end the line.
+    fn optional(&mut self) -> Result<Option<f32>, String> {
+        self.0.next().map(parse).transpose()
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        match self.0.next() {
+            Some(word) => Err(format!("unexpected `{}`", word)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse(word: &str) -> Result<f32, String> {
+    word.parse()
+        .ok()
+        .filter(|number: &f32| number.is_finite())
+        .ok_or_else(|| format!("invalid number `{}`", word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    #[test]
+    fn scene_describes_spheres_and_camera() {
+        let scene = "
+            # A lit ball on the ground.
+            camera 0 1 3  0 0 -1  40 0.1
+            material ground lambertian 0.8 0.8 0.0
+            material lamp light 4 4 4
+            sphere 0 -100.5 -1 100 ground
+            sphere 0 0 -1 0.5 lamp   # Above the ground.
+        "
+        .parse::<Scene>()
+        .unwrap();
+        assert_eq!(
+            scene.view,
+            View {
+                look_from: vec3(0.0, 1.0, 3.0),
+                look_at: vec3(0.0, 0.0, -1.0),
+                vfov: 40.0,
+                aperture: 0.1,
+            }
+        );
+
+        let model = scene.model();
+        let down = Ray::new(vec3(0.0, 2.0, -1.0), vec3(0.0, -1.0, 0.0));
+        let hit = model.hit(&down, 0.0, f32::MAX).unwrap();
+        assert_eq!(hit.material.name(), "diffuse-light");
+        assert!((hit.point.y - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scene_errors_name_their_line() {
+        let errors = [
+            (
+                "sphere 0 0 0 1 glass",
+                "line 1: undescribed material `glass`",
+            ),
+            ("material a lambertian 1 1", "line 1: expected a number"),
+            (
+                "material a plastic 1 1 1",
+                "line 1: unknown material `plastic`",
+            ),
+            ("\ncamera 0 0 0 1 1 1 inf", "line 2: invalid number `inf`"),
+            ("material a dielectric 1.5 2", "line 1: unexpected `2`"),
+            ("cube 0 0 0", "line 1: unknown item `cube`"),
+        ];
+        for &(scene, error) in &errors {
+            assert_eq!(scene.parse::<Scene>().err().as_deref(), Some(error));
+        }
+    }
+}
@@ -0,0 +1,478 @@
+use crate::options::Options;
+
+use raytracer::progress::{CancelToken, Observer, Progress};
+use raytracer::scene::Scene;
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+// Largest request body accepted, enough for scenes of tens of thousands
+// of spheres.
+const MAX_BODY: usize = 1 << 20;
+
+// Largest images and most samples per pixel a job may ask for, so one
+// request cannot tie up the server for days or run it out of memory.
+const MAX_PIXELS: u64 = 8192 * 8192;
+const MAX_SAMPLES: u32 = 1 << 16;
+
+// Options a job may be posted with. They only change how the image is
+// rendered, leaving the files written and the machines connected to
+// the server.
+const JOB_FLAGS: &[&str] = &[
+    "--width",
+    "--height",
+    "--samples",
+    "--min-samples",
+    "--adaptive",
+    "--progressive",
+    "--time-limit",
+    "--sampler",
+    "--seed",
+    "--roulette-depth",
+    "--no-roulette",
+    "--clamp-direct",
+    "--clamp-indirect",
+    "--regularize",
+    "--integrator",
+    "--ao-distance",
+    "--filter",
+    "--filter-radius",
+    "--tile-size",
+    "--tile-order",
+    "--exr-precision",
+    "--exposure",
+    "--white-balance",
+    "--tone-mapper",
+    "--denoise",
+    "--denoise-iterations",
+];
+
+// Where a job is in the queue.
+enum State {
+    Queued,
+    Running,
+    Done,
+    Cancelled,
+    Failed(String),
+}
+
+// A render posted to the server, with the options it was posted with.
+struct Job {
+    args: Vec<String>,
+    state: State,
+    progress: Progress,
+    cancel: CancelToken,
+}
+
+// Keeps the progress of a job up to date as it renders.
+struct JobObserver<'a> {
+    jobs: &'a Mutex<Vec<Job>>,
+    id: usize,
+}
+
+impl Observer for JobObserver<'_> {
+    fn progress(&self, progress: &Progress) {
+        self.jobs.lock().unwrap()[self.id].progress = *progress;
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+/// Serve render jobs over HTTP on the given address, writing their
+/// images to `dir`.
+///
+/// - `POST /jobs` queues a render with the options on the first line of
+///   the body, as given on the command line, and returns its id. The
+///   rest of the body describes the scene, as read by `--scene`, and the
+///   built-in scene is rendered if it is empty. Only options changing how
+///   the image is rendered are accepted, up to a limit on pixels and
+///   samples, and the server names the images.
+/// - `GET /jobs` and `GET /jobs/<id>` return the progress of the jobs.
+/// - `GET /jobs/<id>/image.png` and `GET /jobs/<id>/image.exr` return the
+///   images of a finished job.
+/// - `DELETE /jobs/<id>` cancels a job that has not finished yet.
+///
+/// Jobs are rendered one at a time, in the order they were posted.
+pub fn serve(address: &str, dir: &Path) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    fs::create_dir_all(dir)?;
+    let jobs = Mutex::new(Vec::new());
+    let (queue, pending) = mpsc::channel();
+
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            for id in pending {
+                render(&jobs, id, dir);
+            }
+        });
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let (jobs, queue) = (&jobs, &queue);
+            scope.spawn(move || {
+                if let Err(e) = respond(stream, jobs, queue, dir) {
+                    eprintln!("failed to answer request: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+// Render a queued job, keeping track of its progress.
+fn render(jobs: &Mutex<Vec<Job>>, id: usize, dir: &Path) {
+    let (args, cancel) = {
+        let mut jobs = jobs.lock().unwrap();
+        let job = &mut jobs[id];
+        if matches!(job.state, State::Cancelled) {
+            return;
+        }
+        job.state = State::Running;
+        (job.args.clone(), job.cancel.clone())
+    };
+
+    let observer = JobObserver { jobs, id };
+    let rendered = Options::from_args(args.iter().cloned()).and_then(|mut options| {
+        options.output = job_path(dir, id, "png");
+        options.linear_output = true;
+        crate::run(
+            &options,
+            &Options::job(&args),
+            None,
+            &observer,
+            cancel.clone(),
+        )
+    });
+
+    jobs.lock().unwrap()[id].state = match rendered {
+        Ok(()) if cancel.is_cancelled() => State::Cancelled,
+        Ok(()) => State::Done,
+        Err(e) => State::Failed(e.to_string()),
+    };
+}
+
+// Read a request, answer it and close the connection.
+fn respond(
+    stream: TcpStream,
+    jobs: &Mutex<Vec<Job>>,
+    queue: &Sender<usize>,
+    dir: &Path,
+) -> io::Result<()> {
+    let mut r = BufReader::new(&stream);
+    let mut request = String::new();
+    r.read_line(&mut request)?;
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if r.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap_or(usize::MAX);
+            }
+        }
+    }
+
+    let response = if length > MAX_BODY {
+        error("413 Payload Too Large", "request body is too large")
+    } else {
+        let mut body = vec![0; length];
+        r.read_exact(&mut body)?;
+        route(method, path, &body, jobs, queue, dir)
+    };
+
+    let mut w = &stream;
+    write!(
+        w,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    w.write_all(&response.body)?;
+    w.flush()
+}
+
+fn route(
+    method: &str,
+    path: &str,
+    body: &[u8],
+    jobs: &Mutex<Vec<Job>>,
+    queue: &Sender<usize>,
+    dir: &Path,
+) -> Response {
+    let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+    match (method, segments.as_slice()) {
+        ("POST", ["jobs"]) => {
+            let body = String::from_utf8_lossy(body);
+            let (line, scene) = body.split_once('\n').unwrap_or((&body, ""));
+            let mut args = line
+                .split_whitespace()
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+            // Values never start with `--`, so this catches every flag.
+            let forbidden = args
+                .iter()
+                .find(|arg| arg.starts_with("--") && !JOB_FLAGS.contains(&arg.as_str()));
+            if let Some(flag) = forbidden {
+                let message = format!("option `{}` cannot be used in jobs", flag);
+                return error("400 Bad Request", &message);
+            }
+            let options = match Options::from_args(args.iter().cloned()) {
+                Ok(options) => options,
+                Err(e) => return error("400 Bad Request", &e.to_string()),
+            };
+            let pixels = options.width as u64 * options.height as u64;
+            if pixels > MAX_PIXELS || options.samples > MAX_SAMPLES {
+                let message = format!(
+                    "jobs take at most {} pixels and {} samples per pixel",
+                    MAX_PIXELS, MAX_SAMPLES
+                );
+                return error("400 Bad Request", &message);
+            }
+            let has_scene = !scene.trim().is_empty();
+            if has_scene {
+                if let Err(e) = scene.parse::<Scene>() {
+                    return error("400 Bad Request", &format!("scene: {}", e));
+                }
+            }
+
+            let mut jobs = jobs.lock().unwrap();
+            let id = jobs.len();
+            if has_scene {
+                let path = job_path(dir, id, "scene");
+                if let Err(e) = fs::write(&path, scene) {
+                    return error("500 Internal Server Error", &e.to_string());
+                }
+                args.push("--scene".to_owned());
+                args.push(path.to_string_lossy().into_owned());
+            }
+            jobs.push(Job {
+                args,
+                state: State::Queued,
+                progress: Progress {
+                    pixels_done: 0,
+                    pixels,
+                    samples_done: 0,
+                    samples: pixels * options.samples as u64,
+                    elapsed: Default::default(),
+                },
+                cancel: CancelToken::new(),
+            });
+            if queue.send(id).is_err() {
+                jobs[id].state = State::Failed("the render thread has stopped".to_owned());
+                return error("503 Service Unavailable", "jobs cannot be rendered");
+            }
+            json("201 Created", format!("{{\"id\":{}}}", id))
+        }
+        ("GET", ["jobs"]) => {
+            let jobs = jobs.lock().unwrap();
+            let statuses = jobs
+                .iter()
+                .enumerate()
+                .map(|(id, job)| status(id, job))
+                .collect::<Vec<_>>();
+            json("200 OK", format!("[{}]", statuses.join(",")))
+        }
+        ("GET", ["jobs", id]) => match find(jobs, id) {
+            Some(id) => json("200 OK", status(id, &jobs.lock().unwrap()[id])),
+            None => error("404 Not Found", "no such job"),
+        },
+        ("DELETE", ["jobs", id]) => {
+            let id = match find(jobs, id) {
+                Some(id) => id,
+                None => return error("404 Not Found", "no such job"),
+            };
+            let mut jobs = jobs.lock().unwrap();
+            let job = &mut jobs[id];
+            match job.state {
+                // Running jobs are marked once they stop.
+                State::Queued => job.state = State::Cancelled,
+                State::Running => {}
+                _ => return error("409 Conflict", "job is already finished"),
+            }
+            job.cancel.cancel();
+            json("200 OK", status(id, job))
+        }
+        ("GET", ["jobs", id, image]) => {
+            let (extension, content_type) = match *image {
+                "image.png" => ("png", "image/png"),
+                "image.exr" => ("exr", "image/x-exr"),
+                _ => return error("404 Not Found", "no such image"),
+            };
+            let id = match find(jobs, id) {
+                Some(id) => id,
+                None => return error("404 Not Found", "no such job"),
+            };
+            if !matches!(jobs.lock().unwrap()[id].state, State::Done) {
+                return error("409 Conflict", "job is not done");
+            }
+            match fs::read(job_path(dir, id, extension)) {
+                Ok(body) => Response {
+                    status: "200 OK",
+                    content_type,
+                    body,
+                },
+                Err(e) => error("500 Internal Server Error", &e.to_string()),
+            }
+        }
+        _ => error("404 Not Found", "no such resource"),
+    }
+}
+
+// Parse the id of an existing job.
+fn find(jobs: &Mutex<Vec<Job>>, id: &str) -> Option<usize> {
+    id.parse()
+        .ok()
+        .filter(|&id| id < jobs.lock().unwrap().len())
+}
+
+// Where the file of a job with the given extension is written.
+fn job_path(dir: &Path, id: usize, extension: &str) -> PathBuf {
+    dir.join(format!("job-{}.{}", id, extension))
+}
+
+// Progress of a job as a JSON object.
+fn status(id: usize, job: &Job) -> String {
+    let state = match &job.state {
+        State::Queued => "queued",
+        State::Running => "running",
+        State::Done => "done",
+        State::Cancelled => "cancelled",
+        State::Failed(_) => "failed",
+    };
+    let progress = &job.progress;
+    let eta = match progress.eta() {
+        Some(eta) => eta.as_secs_f32().to_string(),
+        None => "null".to_owned(),
+    };
+    let mut status = format!(
+        "{{\"id\":{},\"state\":\"{}\",\"pixels_done\":{},\"pixels\":{},\
+         \"samples_done\":{},\"samples\":{},\"progress\":{},\"elapsed\":{},\"eta\":{}",
+        id,
+        state,
+        progress.pixels_done,
+        progress.pixels,
+        progress.samples_done,
+        progress.samples,
+        progress.fraction(),
+        progress.elapsed.as_secs_f32(),
+        eta
+    );
+    if let State::Failed(message) = &job.state {
+        status += &format!(",\"error\":{}", json_string(message));
+    }
+    status + "}"
+}
+
+fn json(status: &'static str, body: String) -> Response {
+    Response {
+        status,
+        content_type: "application/json",
+        body: body.into_bytes(),
+    }
+}
+
+fn error(status: &'static str, message: &str) -> Response {
+    json(status, format!("{{\"error\":{}}}", json_string(message)))
+}
+
+// Quote a string for JSON, escaping what it cannot hold as is.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Jobs name neither files nor machines, so a client cannot have the
+    // server write outside of its directory.
+    #[test]
+    fn jobs_take_render_options_only() {
+        let jobs = Mutex::new(Vec::new());
+        let (queue, pending) = mpsc::channel();
+        let dir = Path::new("jobs");
+        let post = |body: &str| route("POST", "/jobs", body.as_bytes(), &jobs, &queue, dir);
+
+        for body in [
+            "--output /etc/passwd",
+            "--samples 4 --checkpoint job.ck",
+            "--stats-json stats.json",
+            "--trace-obj paths.obj",
+            "--coordinator 0.0.0.0:7000",
+            "--worker 10.0.0.1:7000",
+            "--scene /etc/passwd",
+            "--width 100000 --height 100000",
+            "--samples 1000000",
+            "--samples 4\nsphere 0 0 -1 0.5 glass",
+        ] {
+            let response = post(body);
+            assert_eq!(response.status, "400 Bad Request", "{}", body);
+        }
+        assert!(pending.try_recv().is_err());
+
+        let response = post("--width 40 --height 30 --samples 4 --exposure -1");
+        assert_eq!(response.status, "201 Created");
+        assert_eq!(pending.try_recv(), Ok(0));
+        assert_eq!(jobs.lock().unwrap()[0].args.len(), 8);
+
+        drop(pending);
+        let response = post("--samples 4");
+        assert_eq!(response.status, "503 Service Unavailable");
+        assert!(matches!(jobs.lock().unwrap()[1].state, State::Failed(_)));
+    }
+
+    // The scene after the options is written next to the images and
+    // handed to the render with `--scene`.
+    #[test]
+    fn jobs_take_a_scene() {
+        let jobs = Mutex::new(Vec::new());
+        let (queue, pending) = mpsc::channel();
+        let dir = std::env::temp_dir().join(format!("jobs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let scene = "material ground lambertian 0.5 0.5 0.5\nsphere 0 -100.5 -1 100 ground\n";
+        let body = format!("--samples 4\n{}", scene);
+        let response = route("POST", "/jobs", body.as_bytes(), &jobs, &queue, &dir);
+        assert_eq!(response.status, "201 Created");
+        assert_eq!(pending.try_recv(), Ok(0));
+
+        let path = job_path(&dir, 0, "scene");
+        let args = &jobs.lock().unwrap()[0].args;
+        assert_eq!(
+            args[2..],
+            ["--scene".to_owned(), path.to_string_lossy().into_owned()]
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), scene);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
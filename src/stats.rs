@@ -0,0 +1,171 @@
+use crate::model::IntersectionTests;
+
+use std::time::{Duration, Instant};
+
+/// Counts of the rays traced while rendering.
+///
+/// Every rendering thread counts into its own copy, merged once per tile,
+/// so that counting costs next to nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RayStats {
+    /// Rays leaving the camera, one per sample.
+    pub camera_rays: u64,
+    /// Rays continuing paths after a bounce, including the ones scattered
+    /// inside media.
+    pub secondary_rays: u64,
+    /// Rays only checking whether something lies in the way, such as the
+    /// occlusion rays of ambient occlusion.
+    pub shadow_rays: u64,
+    pub intersection_tests: IntersectionTests,
+    /// Number of paths ending after each number of bounces.
+    pub depths: Vec<u64>,
+}
+
+impl RayStats {
+    /// Number of rays traced in all.
+    pub fn rays(&self) -> u64 {
+        self.camera_rays + self.secondary_rays + self.shadow_rays
+    }
+
+    /// Count a path that ended after the given number of bounces.
+    pub fn end_path(&mut self, bounces: usize) {
+        if self.depths.len() <= bounces {
+            self.depths.resize(bounces + 1, 0);
+        }
+        self.depths[bounces] += 1;
+    }
+
+    /// Add the counts of other rays.
+    pub fn merge(&mut self, other: &RayStats) {
+        self.camera_rays += other.camera_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.shadow_rays += other.shadow_rays;
+        self.intersection_tests.spheres += other.intersection_tests.spheres;
+        if self.depths.len() < other.depths.len() {
+            self.depths.resize(other.depths.len(), 0);
+        }
+        for (depth, count) in self.depths.iter_mut().zip(&other.depths) {
+            *depth += count;
+        }
+    }
+}
+
+/// Statistics of a whole render, with the time spent in each of its
+/// phases.
+#[derive(Debug, Clone)]
+pub struct RenderStats {
+    pub rays: RayStats,
+    /// Wall-clock time of every phase so far, in order.
+    pub phases: Vec<(&'static str, Duration)>,
+    // When the current phase started.
+    lap: Instant,
+}
+
+impl Default for RenderStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderStats {
+    /// Start timing the first phase.
+    pub fn new() -> Self {
+        Self {
+            rays: RayStats::default(),
+            phases: Vec::new(),
+            lap: Instant::now(),
+        }
+    }
+
+    /// End the current phase under the given name, and start timing the
+    /// next one.
+    pub fn lap(&mut self, phase: &'static str) {
+        let now = Instant::now();
+        self.phases.push((phase, now - self.lap));
+        self.lap = now;
+    }
+
+    /// Time spent in the phases with the given name.
+    pub fn time(&self, phase: &str) -> Duration {
+        self.phases
+            .iter()
+            .filter(|(name, _)| *name == phase)
+            .map(|(_, time)| *time)
+            .sum()
+    }
+
+    /// Time spent in all of the phases.
+    pub fn total_time(&self) -> Duration {
+        self.phases.iter().map(|(_, time)| *time).sum()
+    }
+
+    /// Rays traced per second spent in the `render` phase.
+    pub fn rays_per_second(&self) -> f64 {
+        self.rays.rays() as f64 / self.time("render").as_secs_f64().max(1e-9)
+    }
+
+    /// The statistics as lines of text, for people to read.
+    pub fn report(&self) -> String {
+        let rays = &self.rays;
+        let paths = rays.depths.iter().sum::<u64>().max(1);
+        let mut report = format!(
+            "camera rays:        {}\n\
+             secondary rays:     {}\n\
+             shadow rays:        {}\n\
+             rays per second:    {:.0}\n\
+             sphere tests:       {}\n\
+             paths by bounces:\n",
+            rays.camera_rays,
+            rays.secondary_rays,
+            rays.shadow_rays,
+            self.rays_per_second(),
+            rays.intersection_tests.spheres,
+        );
+        for (bounces, count) in rays.depths.iter().enumerate() {
+            let share = *count as f64 / paths as f64;
+            report += &format!("  {:>3}  {:>12}  {:5.1}%\n", bounces, count, share * 100.0);
+        }
+        report += "time per phase:\n";
+        for (phase, time) in &self.phases {
+            report += &format!("  {:<8} {:>10.3}s\n", phase, time.as_secs_f64());
+        }
+        report += &format!(
+            "  {:<8} {:>10.3}s\n",
+            "total",
+            self.total_time().as_secs_f64()
+        );
+        report
+    }
+
+    /// The statistics as a JSON object.
+    pub fn to_json(&self) -> String {
+        let rays = &self.rays;
+        let depths = rays.depths.iter().map(u64::to_string).collect::<Vec<_>>();
+        let phases = self
+            .phases
+            .iter()
+            .map(|(phase, time)| {
+                format!(
+                    "{{\"name\":\"{}\",\"seconds\":{}}}",
+                    phase,
+                    time.as_secs_f64()
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"camera_rays\":{},\"secondary_rays\":{},\"shadow_rays\":{},\"rays\":{},\
+             \"rays_per_second\":{},\"intersection_tests\":{{\"spheres\":{},\"total\":{}}},\
+             \"depths\":[{}],\"phases\":[{}],\"seconds\":{}}}",
+            rays.camera_rays,
+            rays.secondary_rays,
+            rays.shadow_rays,
+            rays.rays(),
+            self.rays_per_second(),
+            rays.intersection_tests.spheres,
+            rays.intersection_tests.total(),
+            depths.join(","),
+            phases.join(","),
+            self.total_time().as_secs_f64()
+        )
+    }
+}
@@ -0,0 +1,265 @@
+use crate::model::Hit;
+use crate::vec3::{vec3, Vec3};
+
+use std::path::Path;
+
+// Texture enum so we can avoid dynamic dispatch.
+/// Texture enumeration.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Texture {
+    Constant(Vec3),
+    Checker(Checker),
+    Image(ImageTexture),
+}
+
+impl Texture {
+    /// Convenience method to construct a texture with a single value
+    /// everywhere.
+    pub fn constant(value: Vec3) -> Self {
+        Self::Constant(value)
+    }
+
+    /// Convenience method to construct a solid checker texture.
+    pub fn checker(odd: Vec3, even: Vec3, scale: f32) -> Self {
+        Self::Checker(Checker::new(odd, even, scale))
+    }
+
+    /// Convenience method to load an image texture from a file.
+    pub fn image(path: impl AsRef<Path>) -> image::ImageResult<Self> {
+        Ok(Self::Image(ImageTexture::open(path)?))
+    }
+
+    /// Get the value of the texture at the texture coordinates `uv`
+    /// of a point in space.
+    pub fn value(&self, uv: (f32, f32), point: Vec3) -> Vec3 {
+        match self {
+            Texture::Constant(value) => *value,
+            Texture::Checker(tex) => tex.value(point),
+            Texture::Image(tex) => tex.value(uv),
+        }
+    }
+
+    /// Get the grayscale value of the texture, the average of its channels.
+    pub fn scalar(&self, uv: (f32, f32), point: Vec3) -> f32 {
+        let value = self.value(uv, point);
+        (value.x + value.y + value.z) / 3.0
+    }
+}
+
+/// Solid checker texture alternating between two values in
+/// cubes of side `scale`.
+#[derive(Debug)]
+pub struct Checker {
+    odd: Vec3,
+    even: Vec3,
+    scale: f32,
+}
+
+impl Checker {
+    pub fn new(odd: Vec3, even: Vec3, scale: f32) -> Self {
+        Self { odd, even, scale }
+    }
+
+    pub fn value(&self, point: Vec3) -> Vec3 {
+        let p = point / self.scale;
+        let cell = p.x.floor() as i32 + p.y.floor() as i32 + p.z.floor() as i32;
+        if cell % 2 == 0 {
+            self.even
+        } else {
+            self.odd
+        }
+    }
+}
+
+/// Texture backed by an image, with channels mapped to `[0, 1]`
+/// without any colour space conversion.
+#[derive(Debug)]
+pub struct ImageTexture {
+    width: u32,
+    height: u32,
+    data: Vec<Vec3>,
+}
+
+impl ImageTexture {
+    pub fn new(width: u32, height: u32, data: Vec<Vec3>) -> Self {
+        assert_eq!(data.len(), (width * height) as usize);
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> image::ImageResult<Self> {
+        let img = image::open(path)?.to_rgb();
+        let (width, height) = img.dimensions();
+        let data = img
+            .pixels()
+            .map(|p| vec3(p[0] as f32, p[1] as f32, p[2] as f32) / 255.0)
+            .collect();
+        Ok(Self::new(width, height, data))
+    }
+
+    pub fn value(&self, (u, v): (f32, f32)) -> Vec3 {
+        // Wrap around the edges, and flip v since images
+        // are stored from the top row down.
+        let u = u - u.floor();
+        let v = 1.0 - (v - v.floor());
+        let i = ((u * self.width as f32) as u32).min(self.width - 1);
+        let j = ((v * self.height as f32) as u32).min(self.height - 1);
+        self.data[(j * self.width + i) as usize]
+    }
+}
+
+/// Tangent-space normal map, where the red, green and blue channels
+/// hold the tangent, bitangent and normal components of the new normal.
+#[derive(Debug)]
+pub struct NormalMap {
+    map: Texture,
+    // Scales the deviation from the unperturbed normal.
+    strength: f32,
+}
+
+impl NormalMap {
+    pub fn new(map: Texture, strength: f32) -> Self {
+        Self { map, strength }
+    }
+
+    /// Returns a copy of the hit record with its shading frame
+    /// perturbed by the normal map.
+    pub fn perturb<'mat>(&self, rec: &Hit<'mat>) -> Hit<'mat> {
+        let c = self.map.value(rec.uv, rec.point) * 2.0 - Vec3::ONE;
+        let normal = rec.tangent * (c.x * self.strength)
+            + rec.bitangent * (c.y * self.strength)
+            + rec.shading_normal * c.z;
+        with_shading_normal(rec, normal)
+    }
+}
+
+/// Grayscale height map, where the slope of the height perturbs
+/// the shading normal.
+///
+/// The slope is taken along the texture coordinates for image textures,
+/// and along the tangent and bitangent in scene units for solid textures
+/// looked up by point.
+#[derive(Debug)]
+pub struct BumpMap {
+    map: Texture,
+    // Height of a fully white texel.
+    scale: f32,
+}
+
+impl BumpMap {
+    // Step in texture space and scene units used for the finite
+    // differences.
+    const DELTA: f32 = 1e-3;
+
+    pub fn new(map: Texture, scale: f32) -> Self {
+        Self { map, scale }
+    }
+
+    /// Returns a copy of the hit record with its shading frame
+    /// perturbed by the slope of the height map.
+    pub fn perturb<'mat>(&self, rec: &Hit<'mat>) -> Hit<'mat> {
+        let (u, v) = rec.uv;
+        let height = self.map.scalar(rec.uv, rec.point);
+        let du = self
+            .map
+            .scalar((u + Self::DELTA, v), rec.point + rec.tangent * Self::DELTA);
+        let dv = self.map.scalar(
+            (u, v + Self::DELTA),
+            rec.point + rec.bitangent * Self::DELTA,
+        );
+        let (du, dv) = ((du - height) / Self::DELTA, (dv - height) / Self::DELTA);
+        let normal = rec.shading_normal - (rec.tangent * du + rec.bitangent * dv) * self.scale;
+        with_shading_normal(rec, normal)
+    }
+}
+
+// Replace the shading normal of a hit record, keeping the tangent
+// frame orthonormal around it.
+fn with_shading_normal<'mat>(rec: &Hit<'mat>, normal: Vec3) -> Hit<'mat> {
+    let shading_normal = normal.normalize();
+    let tangent = (rec.tangent - shading_normal * rec.tangent.dot(shading_normal)).normalize();
+    let bitangent = shading_normal.cross(tangent);
+    Hit {
+        shading_normal,
+        tangent,
+        bitangent,
+        ..*rec
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+
+    // A hit on the top of a unit sphere at the origin, with u along x and
+    // v along -z.
+    fn hit<'mat>(material: &'mat Material<'mat>) -> Hit<'mat> {
+        let normal = vec3(0.0, 1.0, 0.0);
+        Hit {
+            parameter: 1.0,
+            point: normal,
+            normal,
+            shading_normal: normal,
+            tangent: vec3(1.0, 0.0, 0.0),
+            bitangent: vec3(0.0, 0.0, -1.0),
+            uv: (0.5, 0.5),
+            object: 1,
+            material,
+        }
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).mag() < 1e-4, "{:?} is not {:?}", a, b);
+    }
+
+    #[test]
+    fn flat_normal_map_keeps_the_normal() {
+        let material = Material::lambertian(Vec3::ONE);
+        let rec = hit(&material);
+        let map = NormalMap::new(Texture::constant(vec3(0.5, 0.5, 1.0)), 1.0);
+        assert_close(map.perturb(&rec).shading_normal, rec.shading_normal);
+    }
+
+    #[test]
+    fn normal_map_tilts_along_the_tangent() {
+        let material = Material::lambertian(Vec3::ONE);
+        let rec = hit(&material);
+        let map = NormalMap::new(Texture::constant(vec3(1.0, 0.5, 1.0)), 1.0);
+        let perturbed = map.perturb(&rec);
+        assert_close(perturbed.shading_normal, vec3(1.0, 1.0, 0.0).normalize());
+        assert!(perturbed.tangent.dot(perturbed.shading_normal).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bump_map_follows_image_slope() {
+        // Height rising along u, so the normal leans back along -u.
+        let ramp = (0..4096).map(|i| Vec3::all(i as f32 / 4095.0)).collect();
+        let map = BumpMap::new(Texture::Image(ImageTexture::new(4096, 1, ramp)), 0.01);
+        let material = Material::lambertian(Vec3::ONE);
+        let rec = hit(&material);
+        let normal = map.perturb(&rec).shading_normal;
+        assert!(normal.x < -1e-3 && normal.z.abs() < 1e-5, "{:?}", normal);
+    }
+
+    #[test]
+    fn bump_map_follows_solid_texture_slope() {
+        // Just before a cell boundary of the checker along the tangent, the
+        // height steps up from the even to the odd value.
+        let checker = Texture::checker(Vec3::ONE, Vec3::ZERO, 1.0);
+        let map = BumpMap::new(checker, 1e-4);
+        let material = Material::lambertian(Vec3::ONE);
+        let mut rec = hit(&material);
+        rec.point = vec3(0.9995, 1.5, -0.5);
+        let normal = map.perturb(&rec).shading_normal;
+        assert!(normal.x < -1e-3, "{:?}", normal);
+
+        // Away from the boundaries the checker is flat.
+        rec.point = vec3(0.5, 1.5, -0.5);
+        assert_close(map.perturb(&rec).shading_normal, rec.shading_normal);
+    }
+}
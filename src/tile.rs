@@ -0,0 +1,244 @@
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Rectangular block of pixels rendered as a unit of work.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    /// Position of the tile in the rendering order.
+    pub index: usize,
+    pub x: Range<u32>,
+    pub y: Range<u32>,
+}
+
+impl Tile {
+    pub fn width(&self) -> u32 {
+        self.x.end - self.x.start
+    }
+
+    pub fn height(&self) -> u32 {
+        self.y.end - self.y.start
+    }
+
+    /// Returns the pixels of the tile in row-major order.
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let x = self.x.clone();
+        self.y
+            .clone()
+            .flat_map(move |y| x.clone().map(move |x| (x, y)))
+    }
+}
+
+/// Order in which tiles are handed out to the workers.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum TileOrder {
+    /// Row by row from the top left.
+    Scanline,
+    /// Outwards from the centre of the image, where the subject
+    /// usually is.
+    #[default]
+    Spiral,
+    /// Along a Hilbert curve, so that consecutive tiles are neighbours.
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            _ => Err(format!("unknown tile order `{}`", s)),
+        }
+    }
+}
+
+/// Split an image into square tiles of the given size, smaller at the
+/// right and bottom edges, and sort them in the given order.
+pub fn tiles(width: u32, height: u32, size: u32, order: TileOrder) -> Vec<Tile> {
+    let size = size.max(1);
+    let columns = width.div_ceil(size);
+    let rows = height.div_ceil(size);
+
+    let cells = match order {
+        TileOrder::Scanline => (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .collect(),
+        TileOrder::Spiral => spiral(columns, rows),
+        TileOrder::Hilbert => {
+            let mut cells = Vec::with_capacity(columns as usize * rows as usize);
+            let (columns, rows) = (columns as i64, rows as i64);
+            if columns >= rows {
+                hilbert(&mut cells, (0, 0), (columns, 0), (0, rows));
+            } else {
+                hilbert(&mut cells, (0, 0), (0, rows), (columns, 0));
+            }
+            cells
+        }
+    };
+
+    cells
+        .into_iter()
+        .enumerate()
+        .map(|(index, (column, row))| Tile {
+            index,
+            x: column * size..((column + 1) * size).min(width),
+            y: row * size..((row + 1) * size).min(height),
+        })
+        .collect()
+}
+
+// Walk around the centre cell in growing squares, keeping the cells
+// that fall inside the grid.
+fn spiral(columns: u32, rows: u32) -> Vec<(u32, u32)> {
+    const DIRECTIONS: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+    let total = columns as usize * rows as usize;
+    let mut cells = Vec::with_capacity(total);
+    let (mut x, mut y) = ((columns / 2) as i64, (rows / 2) as i64);
+    let mut length = 1;
+    let mut direction = 0;
+
+    while cells.len() < total {
+        // Each length is walked twice before it grows.
+        for _ in 0..2 {
+            let (dx, dy) = DIRECTIONS[direction % 4];
+            for _ in 0..length {
+                if x >= 0 && y >= 0 && x < columns as i64 && y < rows as i64 {
+                    cells.push((x as u32, y as u32));
+                }
+                x += dx;
+                y += dy;
+            }
+            direction += 1;
+        }
+        length += 1;
+    }
+    cells
+}
+
+// Walk the rectangle spanned by `a` and `b` from `origin` along a
+// generalized Hilbert curve, after Červený's "gilbert". `a` points along
+// the side the curve starts and ends on. Unlike the curve on a power of
+// two grid, it fills any rectangle while keeping consecutive cells
+// neighbours, apart from one diagonal step in grids with odd sides.
+fn hilbert(cells: &mut Vec<(u32, u32)>, origin: (i64, i64), a: (i64, i64), b: (i64, i64)) {
+    let (x, y) = origin;
+    let width = (a.0 + a.1).abs();
+    let height = (b.0 + b.1).abs();
+    let da = (a.0.signum(), a.1.signum());
+    let db = (b.0.signum(), b.1.signum());
+
+    // A single row or column is walked straight through.
+    if height == 1 || width == 1 {
+        let (steps, d) = if height == 1 {
+            (width, da)
+        } else {
+            (height, db)
+        };
+        for i in 0..steps {
+            cells.push(((x + i * d.0) as u32, (y + i * d.1) as u32));
+        }
+        return;
+    }
+
+    let half = |v: (i64, i64)| (v.0.div_euclid(2), v.1.div_euclid(2));
+    let (mut a2, mut b2) = (half(a), half(b));
+    if 2 * width > 3 * height {
+        // Long rectangles are split in two along their length, with even
+        // halves so that both curves end where the next one starts.
+        if (a2.0 + a2.1).abs() % 2 == 1 && width > 2 {
+            a2 = (a2.0 + da.0, a2.1 + da.1);
+        }
+        hilbert(cells, origin, a2, b);
+        hilbert(cells, (x + a2.0, y + a2.1), (a.0 - a2.0, a.1 - a2.1), b);
+    } else {
+        // Others in three, up the first half of `b`, along `a` and back
+        // down.
+        if (b2.0 + b2.1).abs() % 2 == 1 && height > 2 {
+            b2 = (b2.0 + db.0, b2.1 + db.1);
+        }
+        hilbert(cells, origin, b2, a2);
+        hilbert(cells, (x + b2.0, y + b2.1), a, (b.0 - b2.0, b.1 - b2.1));
+        hilbert(
+            cells,
+            (
+                x + (a.0 - da.0) + (b2.0 - db.0),
+                y + (a.1 - da.1) + (b2.1 - db.1),
+            ),
+            (-b2.0, -b2.1),
+            (-(a.0 - a2.0), -(a.1 - a2.1)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_order_covers_every_pixel_once() {
+        let orders = [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert];
+        // Grids of 7 by 3, 5 by 5 and 1 by 6 tiles, with partial tiles.
+        for &(width, height, size) in &[(100, 37, 16), (80, 80, 16), (10, 90, 16)] {
+            for &order in &orders {
+                let tiles = tiles(width, height, size, order);
+                let mut covered = vec![0; (width * height) as usize];
+                for (index, tile) in tiles.iter().enumerate() {
+                    assert_eq!(tile.index, index);
+                    for (x, y) in tile.pixels() {
+                        covered[(y * width + x) as usize] += 1;
+                    }
+                }
+                assert!(
+                    covered.iter().all(|&count| count == 1),
+                    "{:?} on {}x{}",
+                    order,
+                    width,
+                    height
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_starts_in_the_centre() {
+        let tiles = tiles(100, 37, 16, TileOrder::Spiral);
+        assert_eq!((tiles[0].x.start, tiles[0].y.start), (48, 16));
+    }
+
+    // Every tile of grids of any shape comes once, sharing a side with the
+    // one before it but for a diagonal step in grids with odd sides.
+    #[test]
+    fn consecutive_hilbert_tiles_are_neighbours() {
+        for (columns, rows) in (1..=24).flat_map(|c| (1..=24).map(move |r| (c, r))) {
+            let (width, height) = (columns * 16, rows * 16);
+            let tiles = tiles(width, height, 16, TileOrder::Hilbert);
+            let mut starts = tiles
+                .iter()
+                .map(|tile| (tile.x.start, tile.y.start))
+                .collect::<Vec<_>>();
+            starts.sort_unstable();
+            starts.dedup();
+            assert_eq!(starts.len(), (columns * rows) as usize);
+            let mut diagonal = 0;
+            for pair in tiles.windows(2) {
+                let dx = (pair[0].x.start as i64 - pair[1].x.start as i64).abs();
+                let dy = (pair[0].y.start as i64 - pair[1].y.start as i64).abs();
+                match (dx, dy) {
+                    (16, 0) | (0, 16) => {}
+                    (16, 16) => diagonal += 1,
+                    _ => panic!("{:?} then {:?}", pair[0], pair[1]),
+                }
+            }
+            assert!(
+                diagonal <= 1,
+                "{} diagonal steps on {}x{}",
+                diagonal,
+                width,
+                height
+            );
+        }
+    }
+}
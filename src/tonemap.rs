@@ -0,0 +1,152 @@
+use crate::color;
+use crate::vec3::{vec3, Vec3};
+
+use std::str::FromStr;
+
+/// Operator compressing high dynamic range radiance into the
+/// displayable `[0, 1]` range.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ToneMapper {
+    /// Clip everything above one.
+    #[default]
+    Clamp,
+    /// `x / (1 + x)`, which never quite reaches white.
+    Reinhard,
+    /// Reinhard scaled so that radiance of `white` maps to one.
+    ExtendedReinhard { white: f32 },
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve.
+    Aces,
+    /// John Hable's filmic curve from Uncharted 2.
+    Uncharted2,
+    /// Troy Sobotka's AgX with its default look.
+    Agx,
+}
+
+impl ToneMapper {
+    /// Map linear radiance to linear display values in `[0, 1]`.
+    pub fn apply(self, x: Vec3) -> Vec3 {
+        let x = x.map(|c| c.max(0.0));
+        match self {
+            ToneMapper::Clamp => x,
+            ToneMapper::Reinhard => x.map(|c| c / (1.0 + c)),
+            ToneMapper::ExtendedReinhard { white } => {
+                x.map(|c| c * (1.0 + c / (white * white)) / (1.0 + c))
+            }
+            ToneMapper::Aces => x.map(|c| {
+                // The fit expects the input pre-exposed by 0.6.
+                let c = c * 0.6;
+                (c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)
+            }),
+            ToneMapper::Uncharted2 => {
+                const WHITE: f32 = 11.2;
+                const EXPOSURE_BIAS: f32 = 2.0;
+                x.map(|c| hable(c * EXPOSURE_BIAS) / hable(WHITE))
+            }
+            ToneMapper::Agx => agx(x),
+        }
+        .map(|c| c.clamp(0.0, 1.0))
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "extended-reinhard" => Ok(ToneMapper::ExtendedReinhard { white: 4.0 }),
+            "aces" => Ok(ToneMapper::Aces),
+            "uncharted2" => Ok(ToneMapper::Uncharted2),
+            "agx" => Ok(ToneMapper::Agx),
+            _ => Err(format!("unknown tone mapper `{}`", s)),
+        }
+    }
+}
+
+// Filmic curve used by Uncharted 2.
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+// Minimal AgX, after Benjamin Wrensch's fit of the sigmoid.
+fn agx(x: Vec3) -> Vec3 {
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    let inset = [
+        vec3(0.8424791, 0.04232824, 0.04237565),
+        vec3(0.0784336, 0.8784686, 0.0784336),
+        vec3(0.07922375, 0.07916613, 0.879143),
+    ];
+    let outset = [
+        vec3(1.196879, -0.05289685, -0.05297164),
+        vec3(-0.09802088, 1.151903, -0.09804345),
+        vec3(-0.09902974, -0.09896118, 1.151074),
+    ];
+
+    let encoded = mul(inset, x).map(|c| {
+        let c = (c.max(1e-10).log2() - MIN_EV) / (MAX_EV - MIN_EV);
+        let c = c.clamp(0.0, 1.0);
+        let c2 = c * c;
+        let c4 = c2 * c2;
+        15.5 * c4 * c2 - 40.14 * c4 * c + 31.96 * c4 - 6.868 * c2 * c + 0.4298 * c2 + 0.1191 * c
+            - 0.00232
+    });
+
+    // The sigmoid output is roughly display encoded, so undo that.
+    mul(outset, encoded).map(|c| c.max(0.0).powf(2.2))
+}
+
+// Multiply a vector by a matrix given by its columns.
+fn mul(columns: [Vec3; 3], v: Vec3) -> Vec3 {
+    columns[0] * v.x + columns[1] * v.y + columns[2] * v.z
+}
+
+/// Everything needed to turn the linear radiance of the film into
+/// colours for display.
+#[derive(Debug, Copy, Clone)]
+pub struct DisplayTransform {
+    /// Exposure adjustment in stops.
+    pub exposure: f32,
+    /// Per channel gains correcting the white balance.
+    pub white_balance: Vec3,
+    pub tone_mapper: ToneMapper,
+}
+
+impl DisplayTransform {
+    pub fn new(exposure: f32, tone_mapper: ToneMapper) -> Self {
+        Self {
+            exposure,
+            white_balance: Vec3::ONE,
+            tone_mapper,
+        }
+    }
+
+    /// Set the white balance so that a black body light at the given
+    /// temperature in Kelvin appears neutral.
+    pub fn with_white_balance(mut self, kelvin: f32) -> Self {
+        // Very warm lights have no blue at all, so keep the gains finite.
+        let illuminant = color::blackbody(kelvin).map(|c| c.max(1e-3));
+        self.white_balance = color::blackbody(6504.0) / illuminant;
+        self
+    }
+
+    /// Map linear radiance to sRGB encoded values in `[0, 1]`.
+    pub fn apply(&self, radiance: Vec3) -> Vec3 {
+        let exposed = radiance * self.white_balance * 2f32.powf(self.exposure);
+        self.tone_mapper.apply(exposed).map(color::linear_to_srgb)
+    }
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        Self::new(0.0, ToneMapper::default())
+    }
+}
@@ -27,7 +27,7 @@ impl Vec3 {
     /// Construct a new `Vec3` with three float components.
     ///
     /// # Example
-    /// ```rust
+    /// ```rust,ignore
     /// Vec3::new(1.0, 2.0, 3.0)
     /// ```
     #[must_use]
@@ -40,7 +40,7 @@ impl Vec3 {
     /// all being the same initial value.
     ///
     /// # Example
-    /// ```rust
+    /// ```rust,ignore
     /// Vec3::all(1.0) == Vec3::ONE
     /// ```
     #[must_use]
@@ -53,7 +53,7 @@ impl Vec3 {
     /// the pythagorean theorem.
     /// 
     /// # Example
-    /// ```rust
+    /// ```rust,ignore
     /// Vec3::new(3.0, 4.0, 0.0).mag() == 5.0
     /// ```
     #[must_use]
@@ -66,7 +66,7 @@ impl Vec3 {
     /// using the pythagorean theorem.
     /// 
     /// # Example
-    /// ```rust
+    /// ```rust,ignore
     /// Vec3::new(3.0, 4.0, 0.0).mag_sq() == 25.0
     /// ```
     #[must_use]
@@ -79,7 +79,7 @@ impl Vec3 {
     /// all of the components of the previous vector.
     ///
     /// # Example
-    /// ```rust
+    /// ```rust,ignore
     /// Vec3::new(9.0, 16.0, 25.0).map(f32::sqrt) == Vec3::new(3.0, 4.0, 5.0)
     /// ```
     #[must_use]
@@ -88,11 +88,24 @@ impl Vec3 {
         Self::new(f(self.x), f(self.y), f(self.z))
     }
 
+    /// Returns the largest of the components of the vector.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use raytracer::vec3::Vec3;
+    /// assert_eq!(Vec3::new(0.25, 0.75, 0.5).max_component(), 0.75);
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn max_component(self) -> f32 {
+        self.x.max(self.y).max(self.z)
+    }
+
     /// Returns a new normalized vector where the components of the vector
     /// is scaled so that the magnitude is one, aka. a unit vector.
     /// 
     /// # Example
-    /// ```rust
+    /// ```rust,ignore
     /// Vec3::new(20.0, 0.0, 0.0).normalize() == Vec3::new(1.0, 0.0, 0.0)
     /// 
     /// dbg!(Vec3::ONE.normalize())
@@ -133,6 +146,21 @@ impl Vec3 {
     pub fn reflect(self, normal: Self) -> Self {
         self - self.dot(normal) * normal * 2.0
     }
+
+    /// Returns the linear interpolation between this vector and
+    /// the `rhs` argument vector, where a `t` of zero returns `self`
+    /// and a `t` of one returns `rhs`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use raytracer::vec3::Vec3;
+    /// assert_eq!(Vec3::ZERO.lerp(Vec3::ONE, 0.25), Vec3::all(0.25));
+    /// ```
+    #[must_use]
+    #[inline]
+    pub fn lerp(self, rhs: Self, t: f32) -> Self {
+        self * (1.0 - t) + rhs * t
+    }
 }
 
 impl Mul<Vec3> for f32 {